@@ -0,0 +1,277 @@
+use {
+    clap::{value_parser, Arg, ArgAction, ArgMatches, Command},
+    frcode::{
+        export, Format, FrCompress, FrDecompressBytes, Metadata, OutputMode, Records, Report, SyncIndex, DEFAULT_COMPRESSION_LEVEL,
+        DEFAULT_SORT_MEMORY, DEFAULT_SYNC_INTERVAL,
+    },
+    num_format::{Locale, ToFormattedString},
+    std::error::Error,
+    std::fs::File,
+    std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write},
+    std::path::PathBuf,
+};
+
+fn database_arg() -> Arg {
+    Arg::new("database").required(true).value_parser(value_parser!(PathBuf))
+}
+
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .help("write to OUTPUT instead of stdout")
+        .short('o')
+        .long("output")
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(PathBuf))
+}
+
+fn null_arg(help: &'static str) -> Arg {
+    Arg::new("null").help(help).short('0').long("null").action(ArgAction::SetTrue)
+}
+
+fn open_database(matches: &ArgMatches) -> Result<(PathBuf, BufReader<File>), Box<dyn Error>> {
+    let path: &PathBuf = matches.get_one("database").unwrap();
+    let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok((path.clone(), BufReader::new(file)))
+}
+
+fn output(matches: &ArgMatches) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    Ok(match matches.get_one::<PathBuf>("output") {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(stdout().lock())),
+    })
+}
+
+fn separator(matches: &ArgMatches) -> u8 {
+    if *matches.get_one("null").unwrap() {
+        b'\0'
+    } else {
+        b'\n'
+    }
+}
+
+fn encode(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let reader: Box<dyn BufRead> = match matches.get_one::<PathBuf>("input") {
+        Some(path) if path.as_os_str() != "-" => Box::new(BufReader::new(File::open(path)?)),
+        _ => Box::new(stdin().lock()),
+    };
+    let format = match matches.get_one::<String>("format").map(String::as_str) {
+        Some("locate02") => Format::Locate02,
+        _ => Format::LocateW,
+    };
+    let interval = matches.get_one("sync").copied().unwrap_or(DEFAULT_SYNC_INTERVAL);
+
+    let mut compressed_lines = FrCompress::with_separator(reader, format, separator(matches)).sync_every(interval);
+    if *matches.get_one("sort").unwrap() {
+        compressed_lines = compressed_lines.sorted(DEFAULT_SORT_MEMORY);
+    }
+    if *matches.get_one("zstd").unwrap() {
+        compressed_lines = compressed_lines.compressed(matches.get_one("level").copied().unwrap_or(DEFAULT_COMPRESSION_LEVEL));
+        if !compressed_lines.is_compressed() {
+            return Err("Le format locate02 ne peut pas être compressé avec zstd".into());
+        }
+    }
+    let mut out = output(matches)?;
+    for bytes in compressed_lines {
+        out.write_all(&bytes?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn decode(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let (_, reader) = open_database(matches)?;
+    let mode = if *matches.get_one("json").unwrap() {
+        OutputMode::JsonLines
+    } else if *matches.get_one("null").unwrap() {
+        OutputMode::Nul
+    } else {
+        OutputMode::Lines
+    };
+    export(reader, output(matches)?, mode)?;
+    Ok(())
+}
+
+fn dump(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let (_, reader) = open_database(matches)?;
+    let mut decompressed_lines = FrDecompressBytes::new(reader);
+    let mut out = BufWriter::new(stdout().lock());
+    writeln!(out, "{:>10} {:>7}  suffixe", "octet", "préfixe")?;
+    while let Some(line) = decompressed_lines.next() {
+        let line = line?;
+        let prefix_len = decompressed_lines.prefix_len();
+        write!(out, "{:>10} {:>7}  ", decompressed_lines.offset(), prefix_len)?;
+        out.write_all(&line[prefix_len..])?;
+        match (decompressed_lines.kind(), decompressed_lines.stat()) {
+            (_, Some(stat)) => write!(out, "  [{:?} {} {}]", stat.kind, stat.size, stat.mtime)?,
+            (Some(kind), None) => write!(out, "  [{kind:?}]")?,
+            (None, None) => (),
+        }
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The compression report, with the prefix lengths grouped by powers of 2
+fn print_report(report: &Report, loc: &Locale) {
+    println!("      préfixe commun de {:.1} octets en moyenne", report.mean_prefix_len());
+    for (bucket, entries) in report.prefix_lengths.iter().enumerate() {
+        // Bucket 0 for no prefix, then powers of 2
+        let (start, end) = match bucket {
+            0 => (0, 0),
+            _ => {
+                let start = 1u64 << (bucket - 1);
+                (start, start + (start - 1))
+            }
+        };
+        let range = if start == end { format!("{start}") } else { format!("{start} à {end}") };
+        let unit = if end <= 1 { "octet" } else { "octets" };
+        println!("        {range:>12} {unit:<6} : {} entrées", entries.to_formatted_string(loc));
+    }
+    println!(
+        "      {} comptes sur 2 octets, {} sur 4 octets",
+        report.escapes_2b.to_formatted_string(loc),
+        report.escapes_4b.to_formatted_string(loc)
+    );
+    println!("      entrée la plus longue : {}", String::from_utf8_lossy(&report.longest));
+}
+
+fn info(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let (path, mut reader) = open_database(matches)?;
+    let db_size = reader.get_ref().metadata()?.len();
+    let index = SyncIndex::read(&mut reader)?;
+    let metadata = Metadata::read(&mut reader)?;
+    let reader = BufReader::new(File::open(&path)?);
+
+    // The decompressed size counts a newline per entry
+    let mut decompressed_lines = FrDecompressBytes::new(reader);
+    let (mut entries, mut raw_size) = (0u64, 0u64);
+    for line in decompressed_lines.by_ref() {
+        entries += 1;
+        raw_size += line?.len() as u64 + 1;
+    }
+
+    let loc = &Locale::fr_CA;
+    println!("Base de données {} :", path.display());
+    match (decompressed_lines.format(), decompressed_lines.records()) {
+        (Some(Format::LocateW), Some(Records::Stats)) => println!("      format LOCATEW, avec la taille et la date des entrées"),
+        (Some(Format::LocateW), Some(Records::Kinds)) => println!("      format LOCATEW, avec le type des entrées"),
+        (Some(Format::LocateW), _) => println!("      format LOCATEW"),
+        (Some(Format::Locate02), _) => println!("      format LOCATE02"),
+        (None, _) => println!("      vide"),
+    }
+    println!("      {} entrées", entries.to_formatted_string(loc));
+    println!("      {} octets décompressés", raw_size.to_formatted_string(loc));
+    println!("      {} octets compressés", db_size.to_formatted_string(loc));
+    if decompressed_lines.is_compressed() {
+        println!("      blocs compressés avec zstd");
+    }
+    if raw_size > 0 {
+        println!("      ratio de compression {:.1} %", db_size as f64 * 100.0 / raw_size as f64);
+    }
+    match &index {
+        Some(index) => {
+            println!("      {} blocs de {} entrées", index.blocks().to_formatted_string(loc), index.interval);
+            println!("      sommes de contrôle : {}", if index.checksums.is_some() { "oui" } else { "non" });
+        }
+        None => println!("      sans points de synchronisation"),
+    }
+    if let Some(metadata) = metadata {
+        println!("      générée par {} (horodatage {})", metadata.tool, metadata.created);
+        println!("      racines : {}", metadata.roots.join(" "));
+        if let Some(report) = metadata.report {
+            print_report(&report, loc);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Command::new("frcode")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("compress, decompress and inspect frcode databases")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("encode")
+                .about("compress a list of paths, one per line")
+                .arg(
+                    Arg::new("input")
+                        .help("list of paths, stdin if absent or -")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(output_arg())
+                .arg(null_arg("paths are separated by NUL instead of newline"))
+                .arg(
+                    Arg::new("format")
+                        .help("label and layout of the entries")
+                        .short('f')
+                        .long("format")
+                        .action(ArgAction::Set)
+                        .value_parser(["locatew", "locate02"])
+                        .default_value("locatew"),
+                )
+                .arg(
+                    Arg::new("sort")
+                        .help("sort the paths in byte order and drop the duplicates, with a bounded memory use")
+                        .long("sort")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("sync")
+                        .help("entries between sync points, 0 for none [default: 256]")
+                        .short('s')
+                        .long("sync")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    Arg::new("zstd")
+                        .help("compress each block between sync points with zstd, in the locatew format")
+                        .short('z')
+                        .long("zstd")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("level")
+                        .help("zstd compression level [default: 9]")
+                        .long("level")
+                        .action(ArgAction::Set)
+                        .requires("zstd")
+                        .value_parser(value_parser!(i32).range(1..=22)),
+                ),
+        )
+        .subcommand(
+            Command::new("decode")
+                .about("decompress a database, one path per line")
+                .arg(database_arg())
+                .arg(output_arg())
+                .arg(null_arg("separate paths with NUL instead of newline"))
+                .arg(
+                    Arg::new("json")
+                        .help("print a JSON object per line, with the kind, size and mtime if stored")
+                        .short('j')
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("null"),
+                ),
+        )
+        .subcommand(
+            Command::new("dump")
+                .about("print the offset, prefix length and suffix of each entry")
+                .arg(database_arg()),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("print the format, entry count and compression ratio of a database")
+                .arg(database_arg()),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        Some(("encode", matches)) => encode(matches),
+        Some(("decode", matches)) => decode(matches),
+        Some(("dump", matches)) => dump(matches),
+        Some(("info", matches)) => info(matches),
+        _ => unreachable!("subcommand_required"),
+    }
+}
@@ -0,0 +1,212 @@
+use {
+    crate::{Entry, FrDecompressBytes, FrError, FrWriter, Metadata, Records, Stat, DEFAULT_COMPRESSION_LEVEL, DEFAULT_SYNC_INTERVAL},
+    std::collections::BTreeSet,
+    std::io::{BufRead, Write},
+    std::path::is_separator,
+};
+
+/// Paths to add to, or remove from, a database sorted in byte order
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    added: Vec<(Vec<u8>, Stat)>,
+    removed: BTreeSet<Vec<u8>>,
+    replaced: BTreeSet<Vec<u8>>,
+}
+
+impl Changes {
+    pub fn new() -> Changes {
+        Changes::default()
+    }
+
+    /// Add an entry, or update it if the database already has it. The first one added wins over a duplicate.
+    pub fn add(&mut self, path: impl Into<Vec<u8>>, stat: Stat) {
+        self.added.push((path.into(), stat));
+    }
+
+    /// Remove an entry of the database
+    pub fn remove(&mut self, path: impl Into<Vec<u8>>) {
+        self.removed.insert(path.into());
+    }
+
+    /// Remove `root` and every entry below it. Its new content, if any, is added with `add`.
+    pub fn replace(&mut self, root: impl Into<Vec<u8>>) {
+        let mut root = root.into();
+        while root.last().is_some_and(|b| is_separator(*b as char)) {
+            root.pop();
+        }
+        self.replaced.insert(root);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.replaced.is_empty()
+    }
+
+    /// Whether an entry of the database is removed, by itself or with a replaced subtree
+    fn is_removed(&self, path: &[u8]) -> bool {
+        if self.removed.contains(path) || self.replaced.contains(path) {
+            return true;
+        }
+        // The ancestors of the path, without their trailing separator
+        path.iter()
+            .enumerate()
+            .filter(|(_, b)| is_separator(**b as char))
+            .any(|(i, _)| self.replaced.contains(&path[..i]))
+    }
+}
+
+/// The next entry of the database that isn't removed
+fn next_kept(decompressed_lines: &mut FrDecompressBytes, changes: &Changes) -> Result<Option<(Entry, Option<Stat>)>, FrError> {
+    while let Some(path) = decompressed_lines.next().transpose()? {
+        if !changes.is_removed(&path) {
+            let kind = decompressed_lines.kind();
+            return Ok(Some((Entry { path, kind }, decompressed_lines.stat())));
+        }
+    }
+    Ok(None)
+}
+
+/// Write the database read from `db` with `changes` applied, in one pass. The records of its entries are kept,
+/// with sync points, and so is their compression. An empty `db` gives a database with the metadata of each entry. Returns the number of entries written.
+/// The added entries are merged in byte order. An unsorted database is updated all the same, but an updated entry may be kept twice.
+pub fn apply<W: Write>(db: impl BufRead, mut changes: Changes, writer: W, metadata: Option<Metadata>) -> Result<u64, FrError> {
+    let mut added = std::mem::take(&mut changes.added);
+    added.sort_by(|a, b| a.0.cmp(&b.0)); // stable, the first duplicate stays first
+    added.dedup_by(|a, b| a.0 == b.0);
+    let mut added = added.into_iter().peekable();
+
+    let mut decompressed_lines = FrDecompressBytes::new(db);
+    let mut old = next_kept(&mut decompressed_lines, &changes)?;
+
+    let writer = match (decompressed_lines.format(), decompressed_lines.records()) {
+        (Some(format), Some(Records::Paths)) => FrWriter::with_format(writer, format),
+        (Some(_), Some(Records::Kinds)) => FrWriter::with_kinds(writer),
+        _ => FrWriter::with_stats(writer),
+    };
+    let mut writer = writer.sync_every(DEFAULT_SYNC_INTERVAL);
+    if decompressed_lines.is_compressed() {
+        writer = writer.compressed(DEFAULT_COMPRESSION_LEVEL);
+    }
+    if let Some(metadata) = metadata {
+        writer = writer.with_metadata(metadata);
+    }
+
+    let mut entries = 0;
+    loop {
+        let is_added = match (&old, added.peek()) {
+            (None, None) => break,
+            (Some(_), None) => false,
+            (None, Some(_)) => true,
+            (Some((entry, _)), Some((path, _))) => *path <= entry.path,
+        };
+
+        if is_added {
+            let (path, stat) = added.next().unwrap();
+            if old.as_ref().is_some_and(|(entry, _)| entry.path == path) {
+                old = next_kept(&mut decompressed_lines, &changes)?; // updated
+            }
+            writer.push_stat(&path, stat)?;
+        } else {
+            match old.take().unwrap() {
+                (entry, Some(stat)) => writer.push_stat(&entry.path, stat)?,
+                (entry, None) => writer.push(&entry.path, entry.kind.unwrap_or_default())?,
+            }
+            old = next_kept(&mut decompressed_lines, &changes)?;
+        }
+        entries += 1;
+    }
+
+    writer.finish()?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{Format, FrCompress, Kind},
+        std::io::Cursor,
+    };
+
+    fn stat(kind: Kind, size: u64) -> Stat {
+        Stat { kind, size, mtime: 0 }
+    }
+
+    #[test]
+    fn apply_ok() {
+        let mut writer = FrWriter::with_stats(Vec::new());
+        for (path, kind) in [
+            ("/a", Kind::Dir),
+            ("/a/b", Kind::Dir),
+            ("/a/b-c", Kind::File),
+            ("/a/b/c", Kind::File),
+            ("/a/d", Kind::File),
+            ("/e", Kind::File),
+        ] {
+            writer.push_stat(path.as_bytes(), stat(kind, 1)).unwrap();
+        }
+        let db = writer.finish().unwrap();
+
+        let mut changes = Changes::new();
+        changes.replace("/a/b");
+        changes.add("/a/b", stat(Kind::Dir, 2));
+        changes.add("/a/b/z", stat(Kind::File, 2));
+        changes.remove("/e");
+        changes.add("/f", stat(Kind::File, 2));
+        changes.add("/a/d", stat(Kind::Symlink, 2));
+        changes.add("/a/d", stat(Kind::File, 3));
+        changes.add("/0", stat(Kind::File, 2));
+        let mut applied = Vec::new();
+        assert_eq!(apply(Cursor::new(&db), changes, &mut applied, Some(Metadata::default())).unwrap(), 7);
+
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(&applied));
+        let mut entries = Vec::new();
+        while let Some(path) = decompressed_lines.next() {
+            let stat = decompressed_lines.stat().unwrap();
+            entries.push((String::from_utf8(path.unwrap()).unwrap(), stat.kind, stat.size));
+        }
+        let expected = [
+            ("/0", Kind::File, 2),
+            ("/a", Kind::Dir, 1),
+            ("/a/b", Kind::Dir, 2),
+            ("/a/b-c", Kind::File, 1),
+            ("/a/b/z", Kind::File, 2),
+            ("/a/d", Kind::Symlink, 2),
+            ("/f", Kind::File, 2),
+        ];
+        assert_eq!(entries, expected.map(|(path, kind, size)| (path.to_owned(), kind, size)));
+        let metadata = Metadata::read(&mut Cursor::new(&applied)).unwrap().unwrap();
+        assert_eq!((metadata.entries, metadata.dirs), (7, 2));
+
+        // The metadata of the database, as updatedb passes it, is counted again from scratch
+        let mut changes = Changes::new();
+        changes.add("/g", stat(Kind::File, 2));
+        let mut reapplied = Vec::new();
+        apply(Cursor::new(&applied), changes, &mut reapplied, Some(metadata)).unwrap();
+        let metadata = Metadata::read(&mut Cursor::new(&reapplied)).unwrap().unwrap();
+        assert_eq!((metadata.entries, metadata.dirs, metadata.files, metadata.files_bytes), (8, 2, 6, 22));
+    }
+
+    #[test]
+    fn apply_format_ok() {
+        let db = FrCompress::with_format(Cursor::new("/\n/a\n/a/b\n/b/"), Format::Locate02)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        let mut changes = Changes::new();
+        changes.replace("/a/");
+        changes.add("/c", Stat::default());
+        let mut applied = Vec::new();
+        apply(Cursor::new(&db), changes, &mut applied, None).unwrap();
+
+        let decompressed_lines = FrDecompressBytes::new(Cursor::new(&applied));
+        assert_eq!(decompressed_lines.collect::<Result<Vec<_>, _>>().unwrap(), [&b"/"[..], b"/b/", b"/c"]);
+        assert!(applied.starts_with(b"\0LOCATE02\0"));
+
+        // Everything is added to an empty database
+        let mut changes = Changes::new();
+        changes.add("/a", Stat::default());
+        let mut applied = Vec::new();
+        apply(Cursor::new(b""), changes, &mut applied, None).unwrap();
+        assert_eq!(FrDecompressBytes::new(Cursor::new(&applied)).entries().count(), 1);
+    }
+}
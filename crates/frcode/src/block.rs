@@ -0,0 +1,152 @@
+// A compressed database has COMPRESSED set in the byte following its LOCATEW label.
+// Each sync block is then stored as a BLOCK control record, whose payload is a zstd frame of the records
+// of the block, front coded as in an uncompressed database. The front coding restarts at each block.
+// The header and the footer aren't compressed, and the index points to the BLOCK control records.
+
+use {
+    std::io::{self, Read},
+    zstd::stream::read::Decoder,
+};
+
+/// Flag of the records byte
+pub(crate) const COMPRESSED: u8 = 0x40;
+
+/// zstd level used by updatedb and `apply`. Higher levels barely shrink blocks of a few kilobytes.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 9;
+
+/// Largest decompressed block accepted, far above 256 entries of the longest paths
+pub(crate) const MAX_BLOCK_LEN: u64 = 64 << 20;
+
+pub(crate) fn compress_block(block: &[u8], level: i32) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(block, level)
+}
+
+/// Decompress the payload of a BLOCK control record into `block`
+pub(crate) fn decompress_block(payload: &[u8], block: &mut Vec<u8>) -> io::Result<()> {
+    block.clear();
+    read_block(Decoder::with_buffer(payload)?, block)
+}
+
+/// Read a decompressed block, failing past MAX_BLOCK_LEN bytes rather than exhausting the memory
+pub(crate) fn read_block(decoder: impl Read, block: &mut Vec<u8>) -> io::Result<()> {
+    decoder.take(MAX_BLOCK_LEN + 1).read_to_end(block)?;
+    if block.len() as u64 > MAX_BLOCK_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bloc décompressé trop grand"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{verify, Format, FrCompress, FrDecompressBytes, FrDecompressSlice, FrError, FrWriter, Kind, Stat, SyncIndex},
+        std::io::Cursor,
+    };
+
+    fn dirlist() -> Vec<String> {
+        (0..1000)
+            .map(|i| format!("/home/node_modules/{:03}/index.js.{}", i / 10, i % 10))
+            .collect()
+    }
+
+    #[test]
+    fn compressed_ok() {
+        let dirlist = dirlist();
+        let lines = || Cursor::new(dirlist.join("\n"));
+        let plain = FrCompress::new(lines()).sync_every(64).collect::<Result<Vec<_>, _>>().unwrap().concat();
+        let db = FrCompress::new(lines())
+            .sync_every(64)
+            .compressed(DEFAULT_COMPRESSION_LEVEL)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        assert!(db.len() < plain.len() / 2);
+
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(&db));
+        let lines = decompressed_lines.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(lines.iter().map(|line| line.as_slice()).eq(dirlist.iter().map(|line| line.as_bytes())));
+        assert!(decompressed_lines.is_compressed());
+
+        let mut decompressed_lines = FrDecompressSlice::new(&db).unwrap();
+        let mut count = 0;
+        decompressed_lines
+            .for_each_entry(|line, _, _| {
+                assert_eq!(line, dirlist[count].as_bytes());
+                count += 1;
+                true
+            })
+            .unwrap();
+        assert_eq!(count, dirlist.len());
+
+        // Each block decodes on its own
+        let mut reader = Cursor::new(&db);
+        let index = SyncIndex::read(&mut reader).unwrap().unwrap();
+        assert_eq!(index.blocks(), 16);
+        for block in [0, 5, 15] {
+            let mut decompressed_lines = FrDecompressSlice::at_sync_point(&db, &index, block).unwrap();
+            assert_eq!(decompressed_lines.next_entry().unwrap().unwrap(), dirlist[block * 64].as_bytes());
+            let mut decompressed_lines = FrDecompressBytes::at_sync_point(&mut reader, &index, block).unwrap();
+            assert_eq!(decompressed_lines.next().unwrap().unwrap(), dirlist[block * 64].as_bytes());
+        }
+        assert!(index.check_blocks(&db, 0..index.blocks()).is_ok());
+        assert_eq!(verify(&mut Cursor::new(&db)).unwrap().entries, 1000);
+
+        // A corrupt frame
+        let mut rotten = db.clone();
+        rotten[index.offsets[5] as usize + 12] ^= 0xff;
+        assert!(FrDecompressBytes::new(Cursor::new(&rotten)).any(|line| line.is_err()));
+
+        // A length past the end of the database
+        let mut rotten = db.clone();
+        let len_pos = index.offsets[5] as usize + 8;
+        rotten[len_pos..len_pos + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(FrDecompressBytes::new(Cursor::new(&rotten)).any(|line| matches!(line, Err(FrError::Truncated { .. }))));
+    }
+
+    #[test]
+    fn decompression_bomb_err() {
+        let bomb = compress_block(&vec![b'a'; MAX_BLOCK_LEN as usize + 1], 1).unwrap();
+        assert!(bomb.len() < 100_000);
+        let mut block = Vec::new();
+        assert!(decompress_block(&bomb, &mut block).is_err());
+        assert!(decompress_block(&compress_block(b"/a\n", 1).unwrap(), &mut block).is_ok());
+    }
+
+    #[test]
+    fn compressed_stats_ok() {
+        let mut writer = FrWriter::with_stats(Vec::new()).compressed(DEFAULT_COMPRESSION_LEVEL);
+        for (i, path) in dirlist().iter().enumerate() {
+            let stat = Stat {
+                kind: Kind::File,
+                size: i as u64,
+                mtime: 1_700_000_000 + i as i64,
+            };
+            writer.push_stat(path.as_bytes(), stat).unwrap();
+        }
+        let db = writer.finish().unwrap();
+
+        // Sync points by default
+        let index = SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap();
+        assert_eq!(index.blocks(), 4);
+        let mut decompressed_lines = FrDecompressSlice::at_sync_point(&db, &index, 3).unwrap();
+        let mut sizes = Vec::new();
+        decompressed_lines
+            .for_each_entry(|_, _, stat| {
+                sizes.push(stat.unwrap().size);
+                true
+            })
+            .unwrap();
+        assert!(sizes.into_iter().eq(768..1000));
+
+        // The Locate02 label can't flag it
+        let db = FrCompress::with_format(Cursor::new("/a\n/b"), Format::Locate02)
+            .compressed(DEFAULT_COMPRESSION_LEVEL)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        assert!(db.starts_with(b"\0LOCATE02\0"));
+        assert!(!FrCompress::with_format(Cursor::new(""), Format::Locate02).compressed(1).is_compressed());
+        assert_eq!(SyncIndex::read(&mut Cursor::new(&db)).unwrap(), None);
+    }
+}
@@ -0,0 +1,181 @@
+// CHECKSUM control record payload : the CRC-32 (4 bytes big-endian) of each section of the database, in order :
+//   the header, up to the first sync point or the footer
+//   each sync block, up to the next one or the footer
+//   the control records of the footer preceding the CHECKSUM record
+
+use {
+    crate::{
+        index::{read_footer, CHECKSUM, TRAILER_LEN},
+        FrDecompressBytes, FrError, SyncIndex,
+    },
+    std::io::{BufReader, Read, Seek, SeekFrom},
+};
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE), as computed by zip and gzip
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32(u32::MAX)
+    }
+}
+
+impl Crc32 {
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ *byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn value(self) -> u32 {
+        !self.0
+    }
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::default();
+    crc.update(bytes);
+    crc.value()
+}
+
+/// What `verify` found in an intact database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verified {
+    pub entries: u64,
+    /// False if the database was written without checksums, only its entries were decoded
+    pub checksums: bool,
+}
+
+/// Check the checksums of the database, then decode all its entries
+pub fn verify<R: Read + Seek>(reader: &mut R) -> Result<Verified, FrError> {
+    let footer = read_footer(reader)?;
+    let checksums = footer.as_ref().and_then(|footer| footer.control(CHECKSUM));
+    if let (Some(footer), Some(checksum)) = (&footer, checksums) {
+        if checksum.payload.len() % 4 != 0 {
+            return Err(footer.invalid());
+        }
+
+        // The boundaries of the sections
+        let offsets = SyncIndex::from_footer(footer)?.map(|index| index.offsets).unwrap_or_default();
+        let mut bounds = vec![0];
+        bounds.extend(offsets);
+        bounds.extend([footer.pos, checksum.pos]);
+        if bounds.len() - 1 != checksum.payload.len() / 4 {
+            return Err(footer.invalid());
+        }
+
+        reader.seek(SeekFrom::Start(0))?;
+        let mut section = Vec::new();
+        for (bounds, expected) in bounds.windows(2).zip(checksum.payload.chunks_exact(4)) {
+            section.clear();
+            reader.by_ref().take(bounds[1] - bounds[0]).read_to_end(&mut section)?;
+            if crc32(&section) != u32::from_be_bytes(expected.try_into().unwrap()) {
+                return Err(FrError::BadChecksum { offset: bounds[0] });
+            }
+        }
+    }
+
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut decompressed_lines = FrDecompressBytes::new(BufReader::new(reader));
+    let mut entries = 0;
+    for line in decompressed_lines.by_ref() {
+        line?;
+        entries += 1;
+    }
+
+    // The entries end at the trailer, or at the end of a database without footer
+    let end = if footer.is_some() { len - TRAILER_LEN } else { len };
+    if decompressed_lines.pos != end {
+        return Err(FrError::Truncated {
+            offset: decompressed_lines.pos,
+            entry: entries,
+        });
+    }
+
+    Ok(Verified {
+        entries,
+        checksums: checksums.is_some(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, Metadata},
+        std::io::Cursor,
+    };
+
+    #[test]
+    fn crc32_ok() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn verify_ok() {
+        let dirlist = (0..100).map(|i| format!("/home/{:02}/{}", i / 8, i)).collect::<Vec<String>>();
+        for (interval, metadata) in [(8, None), (0, Some(Metadata::default())), (8, Some(Metadata::default()))] {
+            let mut compressed_lines = FrCompress::new(Cursor::new(dirlist.join("\n"))).sync_every(interval);
+            if let Some(metadata) = metadata {
+                compressed_lines = compressed_lines.with_metadata(metadata);
+            }
+            let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+            let verified = Verified {
+                entries: 100,
+                checksums: true,
+            };
+            assert_eq!(verify(&mut Cursor::new(&db)).unwrap(), verified);
+        }
+
+        let db = FrCompress::new(Cursor::new("/a\n/b")).collect::<Result<Vec<_>, _>>().unwrap().concat();
+        let verified = Verified {
+            entries: 2,
+            checksums: false,
+        };
+        assert_eq!(verify(&mut Cursor::new(&db)).unwrap(), verified);
+    }
+
+    #[test]
+    fn bit_rot_err() {
+        let dirlist = (0..100).map(|i| format!("/home/{:02}/{}", i / 8, i)).collect::<Vec<String>>();
+        let compressed_lines = FrCompress::new(Cursor::new(dirlist.join("\n"))).sync_every(8);
+        let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+        let index = SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap();
+
+        // A flipped bit in a path decodes fine, but not its block
+        let mut rotten = db.clone();
+        let pos = index.offsets[3] as usize + 5;
+        rotten[pos] ^= 0x20;
+        assert!(matches!(verify(&mut Cursor::new(&rotten)), Err(FrError::BadChecksum { offset }) if offset == index.offsets[3]));
+        assert!(index.check_blocks(&db, 0..index.blocks()).is_ok());
+        assert!(index.check_blocks(&rotten, 0..3).is_ok());
+        assert!(matches!(index.check_blocks(&rotten, 2..5), Err(FrError::BadChecksum { .. })));
+
+        // The footer too
+        let mut rotten = db.clone();
+        let pos = db.len() - 40;
+        rotten[pos] ^= 1;
+        assert!(verify(&mut Cursor::new(&rotten)).is_err());
+
+        assert!(matches!(verify(&mut Cursor::new(&db[..db.len() - 1])), Err(FrError::Truncated { .. })));
+    }
+}
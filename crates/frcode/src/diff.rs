@@ -0,0 +1,157 @@
+use {
+    crate::{FrDecompressBytes, FrError},
+    std::cmp::Ordering,
+    std::io::BufRead,
+};
+
+/// A path found in only one of the databases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(Vec<u8>),
+    Removed(Vec<u8>),
+}
+
+/// The next path of a database, checking it's sorted
+struct Side<'a> {
+    decompressed_lines: FrDecompressBytes<'a>,
+    head: Option<Vec<u8>>,
+}
+
+impl<'a> Side<'a> {
+    fn advance(&mut self) -> Result<(), FrError> {
+        loop {
+            let Some(line) = self.decompressed_lines.next().transpose()? else {
+                self.head = None;
+                return Ok(());
+            };
+            match self.head.as_deref().map(|head| line.as_slice().cmp(head)) {
+                Some(Ordering::Equal) => continue, // duplicate
+                Some(Ordering::Less) => {
+                    return Err(FrError::Unsorted {
+                        entry: self.decompressed_lines.entry - 1,
+                    })
+                }
+                _ => {
+                    self.head = Some(line);
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// The paths added and removed between two databases sorted in byte order, in a single pass over both
+pub struct FrDiff<'a> {
+    init: bool,
+    done: bool,
+    old: Side<'a>,
+    new: Side<'a>,
+}
+
+impl<'a> FrDiff<'a> {
+    pub fn new(old: impl BufRead + 'a, new: impl BufRead + 'a) -> FrDiff<'a> {
+        let side = |reader| Side {
+            decompressed_lines: reader,
+            head: None,
+        };
+        FrDiff {
+            init: false,
+            done: false,
+            old: side(FrDecompressBytes::new(old)),
+            new: side(FrDecompressBytes::new(new)),
+        }
+    }
+
+    fn next_change(&mut self) -> Result<Option<Change>, FrError> {
+        if !self.init {
+            self.old.advance()?;
+            self.new.advance()?;
+            self.init = true;
+        }
+
+        loop {
+            let ordering = match (&self.old.head, &self.new.head) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(old), Some(new)) => old.cmp(new),
+            };
+            match ordering {
+                Ordering::Less => {
+                    let removed = self.old.head.clone().unwrap();
+                    self.old.advance()?;
+                    return Ok(Some(Change::Removed(removed)));
+                }
+                Ordering::Greater => {
+                    let added = self.new.head.clone().unwrap();
+                    self.new.advance()?;
+                    return Ok(Some(Change::Added(added)));
+                }
+                Ordering::Equal => {
+                    self.old.advance()?;
+                    self.new.advance()?;
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for FrDiff<'a> {
+    type Item = Result<Change, FrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.next_change() {
+            Ok(Some(change)) => Some(Ok(change)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::FrCompress, std::io::Cursor};
+
+    fn db(lines: &[&str]) -> Vec<u8> {
+        let compressed_lines = FrCompress::new(Cursor::new(lines.join("\n")));
+        compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat()
+    }
+
+    #[test]
+    fn diff_ok() {
+        let old = db(&["C:\\", "C:\\a", "C:\\b", "C:\\b", "C:\\d", "D:\\x"]);
+        let new = db(&["C:\\", "C:\\b", "C:\\c", "C:\\d", "E:\\y", "E:\\z"]);
+        let changes = FrDiff::new(Cursor::new(&old), Cursor::new(&new)).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            changes,
+            [
+                Change::Removed(b"C:\\a".to_vec()),
+                Change::Added(b"C:\\c".to_vec()),
+                Change::Removed(b"D:\\x".to_vec()),
+                Change::Added(b"E:\\y".to_vec()),
+                Change::Added(b"E:\\z".to_vec()),
+            ]
+        );
+
+        assert_eq!(FrDiff::new(Cursor::new(&old), Cursor::new(&old)).count(), 0);
+        assert_eq!(FrDiff::new(Cursor::new(db(&[])), Cursor::new(&old)).count(), 5);
+    }
+
+    #[test]
+    fn unsorted_err() {
+        let old = db(&["/a", "/c", "/b"]);
+        let mut changes = FrDiff::new(Cursor::new(&old), Cursor::new(db(&["/a"])));
+        assert!(matches!(changes.next(), Some(Err(FrError::Unsorted { entry: 2 }))));
+        assert!(changes.next().is_none());
+    }
+}
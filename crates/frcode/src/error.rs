@@ -0,0 +1,74 @@
+use std::{error::Error, fmt, io};
+
+/// Errors raised while compressing or decompressing a database.
+/// `offset` is the byte position in the compressed stream, `entry` the index of the entry being processed.
+#[derive(Debug)]
+pub enum FrError {
+    /// The stream doesn't start with a known label
+    BadHeader,
+    /// The stream ends in the middle of an entry
+    Truncated {
+        offset: u64,
+        entry: u64,
+    },
+    /// The offset-differential count points outside the previous entry, or a record is malformed
+    InvalidOffset {
+        offset: u64,
+        entry: u64,
+    },
+    /// The entry is too long to be encoded
+    Oversized {
+        entry: u64,
+        len: usize,
+    },
+    /// The entry isn't valid UTF-8
+    InvalidUtf8 {
+        offset: u64,
+        entry: u64,
+    },
+    /// The entry sorts before the previous one, in a database expected to be sorted in byte order
+    Unsorted {
+        entry: u64,
+    },
+    /// The checksum of the section starting at `offset` doesn't match its bytes
+    BadChecksum {
+        offset: u64,
+    },
+    /// The sync point `block` is past the last one of the index
+    NoBlock {
+        block: usize,
+        blocks: usize,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for FrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrError::BadHeader => write!(f, "Fichier updateDB invalide"),
+            FrError::Truncated { offset, entry } => write!(f, "Entrée {entry} tronquée à l'octet {offset}"),
+            FrError::InvalidOffset { offset, entry } => write!(f, "Décalage invalide pour l'entrée {entry} à l'octet {offset}"),
+            FrError::Oversized { entry, len } => write!(f, "L'entrée {entry} est trop longue ({len} octets)"),
+            FrError::InvalidUtf8 { offset, entry } => write!(f, "L'entrée {entry} à l'octet {offset} n'est pas en UTF-8"),
+            FrError::Unsorted { entry } => write!(f, "L'entrée {entry} n'est pas triée"),
+            FrError::BadChecksum { offset } => write!(f, "Somme de contrôle invalide pour le bloc à l'octet {offset}"),
+            FrError::NoBlock { block, blocks } => write!(f, "Le bloc {block} n'existe pas, l'index en compte {blocks}"),
+            FrError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for FrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FrError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FrError {
+    fn from(err: io::Error) -> Self {
+        FrError::Io(err)
+    }
+}
@@ -0,0 +1,133 @@
+use {
+    crate::{FrDecompressBytes, FrError, Kind, Stat},
+    std::io::{BufRead, Write},
+};
+
+/// How `export` and `decompress_file` write the entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// One path per line
+    #[default]
+    Lines,
+    /// Paths terminated by NUL, as read by `xargs -0`
+    Nul,
+    /// A JSON object per line, with the kind, size and mtime of the entry if the database stores them.
+    /// Paths that aren't valid UTF-8 are converted lossily.
+    JsonLines,
+}
+
+/// What `export` wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Exported {
+    pub entries: u64,
+    /// Bytes written, separators included
+    pub bytes: u64,
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::File => "file",
+        Kind::Dir => "dir",
+        Kind::Symlink => "symlink",
+        Kind::Other => "other",
+    }
+}
+
+fn push_json_str(out_bytes: &mut Vec<u8>, s: &str) {
+    out_bytes.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => out_bytes.extend_from_slice(b"\\\""),
+            '\\' => out_bytes.extend_from_slice(b"\\\\"),
+            '\n' => out_bytes.extend_from_slice(b"\\n"),
+            '\r' => out_bytes.extend_from_slice(b"\\r"),
+            '\t' => out_bytes.extend_from_slice(b"\\t"),
+            c if (c as u32) < 0x20 => out_bytes.extend_from_slice(format!("\\u{:04x}", c as u32).as_bytes()),
+            c => out_bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    out_bytes.push(b'"');
+}
+
+fn push_entry(out_bytes: &mut Vec<u8>, path: &[u8], kind: Option<Kind>, stat: Option<Stat>, mode: OutputMode) {
+    match mode {
+        OutputMode::Lines | OutputMode::Nul => {
+            out_bytes.extend_from_slice(path);
+            out_bytes.push(if mode == OutputMode::Nul { b'\0' } else { b'\n' });
+        }
+        OutputMode::JsonLines => {
+            out_bytes.extend_from_slice(b"{\"path\":");
+            push_json_str(out_bytes, &String::from_utf8_lossy(path));
+            if let Some(kind) = kind {
+                out_bytes.extend_from_slice(format!(",\"kind\":\"{}\"", kind_name(kind)).as_bytes());
+            }
+            if let Some(stat) = stat {
+                out_bytes.extend_from_slice(format!(",\"size\":{},\"mtime\":{}", stat.size, stat.mtime).as_bytes());
+            }
+            out_bytes.extend_from_slice(b"}\n");
+        }
+    }
+}
+
+/// Decompress the database read from `reader` into `writer`, in the given `mode`
+pub fn export<W: Write>(reader: impl BufRead, mut writer: W, mode: OutputMode) -> Result<Exported, FrError> {
+    let mut decompressed_lines = FrDecompressBytes::new(reader);
+    let mut exported = Exported::default();
+    let mut out_bytes = Vec::with_capacity(1_000);
+    while let Some(line) = decompressed_lines.next() {
+        out_bytes.clear();
+        let line = line?;
+        push_entry(&mut out_bytes, &line, decompressed_lines.kind(), decompressed_lines.stat(), mode);
+        writer.write_all(&out_bytes)?;
+        exported.entries += 1;
+        exported.bytes += out_bytes.len() as u64;
+    }
+    writer.flush()?;
+
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, FrWriter},
+        std::io::Cursor,
+    };
+
+    #[test]
+    fn export_ok() {
+        let db = FrCompress::new(Cursor::new("/a\n/a/b")).collect::<Result<Vec<_>, _>>().unwrap().concat();
+        let mut out = Vec::new();
+        assert_eq!(
+            export(Cursor::new(&db), &mut out, OutputMode::Lines).unwrap(),
+            Exported { entries: 2, bytes: 8 }
+        );
+        assert_eq!(out, b"/a\n/a/b\n");
+
+        let mut out = Vec::new();
+        export(Cursor::new(&db), &mut out, OutputMode::Nul).unwrap();
+        assert_eq!(out, b"/a\0/a/b\0");
+
+        let mut writer = FrWriter::with_stats(Vec::new());
+        let stat = Stat {
+            kind: Kind::Dir,
+            size: 4096,
+            mtime: -1,
+        };
+        writer.push_stat("/\"été\"\n\u{1}".as_bytes(), stat).unwrap();
+        let db = writer.finish().unwrap();
+        let mut out = Vec::new();
+        export(Cursor::new(&db), &mut out, OutputMode::JsonLines).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"path\":\"/\\\"été\\\"\\n\\u0001\",\"kind\":\"dir\",\"size\":4096,\"mtime\":-1}\n"
+        );
+
+        let mut writer = FrWriter::with_kinds(Vec::new());
+        writer.push(b"/a", Kind::Symlink).unwrap();
+        let mut out = Vec::new();
+        export(Cursor::new(&writer.finish().unwrap()), &mut out, OutputMode::JsonLines).unwrap();
+        assert_eq!(out, b"{\"path\":\"/a\",\"kind\":\"symlink\"}\n");
+    }
+}
@@ -0,0 +1,318 @@
+// Control records share the count slot of an entry : the count i32::MIN, then a tag byte,
+// the payload length (4 bytes big-endian) and the payload. Decoders skip unknown tags.
+// The BLOCK control records of a compressed database hold its entries (see block.rs).
+//
+// A database written with sync points or metadata ends with a footer :
+//   INDEX control record : sync interval (4 bytes) then the offset of each sync point (8 bytes each)
+//   META control record : see metadata.rs
+//   CHECKSUM control record : see checksum.rs
+//   END control record : no more entries
+//   trailer : position of the footer (8 bytes) then FOOTER_MAGIC
+// All integers are big-endian.
+
+use {
+    crate::{checksum::crc32, push_count, Format, FrDecompressBytes, FrError, Records},
+    std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom},
+    std::ops::Range,
+};
+
+pub(crate) const CONTROL: i32 = i32::MIN;
+pub(crate) const END: u8 = 0;
+pub(crate) const INDEX: u8 = 1;
+pub(crate) const META: u8 = 2;
+pub(crate) const CHECKSUM: u8 = 3;
+pub(crate) const BLOCK: u8 = 4;
+
+const FOOTER_MAGIC: &[u8; 8] = b"FRFOOTER";
+pub(crate) const TRAILER_LEN: u64 = 16;
+
+pub(crate) fn push_control(out_bytes: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    push_count(out_bytes, CONTROL);
+    out_bytes.push(tag);
+    out_bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out_bytes.extend_from_slice(payload);
+}
+
+pub(crate) fn push_trailer(out_bytes: &mut Vec<u8>, footer_pos: u64) {
+    out_bytes.extend_from_slice(&footer_pos.to_be_bytes());
+    out_bytes.extend_from_slice(FOOTER_MAGIC);
+}
+
+pub(crate) struct Control {
+    pub tag: u8,
+    pub pos: u64,
+    pub payload: Vec<u8>,
+}
+
+pub(crate) struct Footer {
+    pub format: Format,
+    pub records: Records,
+    pub pos: u64,
+    pub controls: Vec<Control>,
+}
+
+impl Footer {
+    pub fn control(&self, tag: u8) -> Option<&Control> {
+        self.controls.iter().find(|control| control.tag == tag)
+    }
+
+    pub fn record(&self, tag: u8) -> Option<&[u8]> {
+        self.control(tag).map(|control| control.payload.as_slice())
+    }
+
+    pub fn invalid(&self) -> FrError {
+        FrError::InvalidOffset { offset: self.pos, entry: 0 }
+    }
+}
+
+/// Read the control records of the footer. `None` if the database doesn't have one.
+pub(crate) fn read_footer<R: Read + Seek>(reader: &mut R) -> Result<Option<Footer>, FrError> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len < TRAILER_LEN {
+        return Ok(None);
+    }
+
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    reader.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    reader.read_exact(&mut trailer)?;
+    if &trailer[8..] != FOOTER_MAGIC {
+        return Ok(None);
+    }
+    let footer_pos = u64::from_be_bytes(trailer[..8].try_into().unwrap());
+    if footer_pos > len - TRAILER_LEN {
+        return Err(FrError::BadHeader);
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let (format, records) = {
+        let mut header = FrDecompressBytes::new(BufReader::new(reader.by_ref().take(footer_pos)));
+        header.read_header()?;
+        (header.format().ok_or(FrError::BadHeader)?, header.records().unwrap_or_default())
+    };
+
+    reader.seek(SeekFrom::Start(footer_pos))?;
+    let mut footer = BufReader::new(reader.by_ref().take(len - TRAILER_LEN - footer_pos));
+    let truncated = |err: std::io::Error| match err.kind() {
+        ErrorKind::UnexpectedEof => FrError::Truncated {
+            offset: footer_pos,
+            entry: 0,
+        },
+        _ => err.into(),
+    };
+
+    let mut expected = Vec::new();
+    push_count(&mut expected, CONTROL);
+    let mut control = [0u8; 12];
+    let mut controls = Vec::new();
+    let mut pos = footer_pos;
+    loop {
+        footer.read_exact(&mut control).map_err(truncated)?;
+        if control[..7] != expected[..] {
+            return Err(FrError::InvalidOffset {
+                offset: footer_pos,
+                entry: 0,
+            });
+        }
+
+        let tag = control[7];
+        let len = u32::from_be_bytes(control[8..].try_into().unwrap()) as u64;
+        if tag == END {
+            break;
+        }
+        let mut payload = Vec::new();
+        footer.by_ref().take(len).read_to_end(&mut payload)?;
+        if payload.len() as u64 != len {
+            return Err(truncated(ErrorKind::UnexpectedEof.into()));
+        }
+        controls.push(Control { tag, pos, payload });
+        pos += control.len() as u64 + len;
+    }
+
+    Ok(Some(Footer {
+        format,
+        records,
+        pos: footer_pos,
+        controls,
+    }))
+}
+
+/// Byte offsets of the sync points, where a full path restarts the front coding.
+/// The first entry of a block is at `offsets[block]`, and is entry number `block * interval`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncIndex {
+    pub format: Format,
+    pub records: Records,
+    pub interval: u32,
+    pub offsets: Vec<u64>,
+    /// Where the last block ends
+    pub end: u64,
+    /// The CRC-32 of each block, if the database has checksums
+    pub checksums: Option<Vec<u32>>,
+}
+
+impl SyncIndex {
+    /// Read the footer of a database. `None` if it was written without sync points.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Option<SyncIndex>, FrError> {
+        match read_footer(reader)? {
+            Some(footer) => SyncIndex::from_footer(&footer),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn from_footer(footer: &Footer) -> Result<Option<SyncIndex>, FrError> {
+        let Some(payload) = footer.record(INDEX) else {
+            return Ok(None);
+        };
+        if payload.len() < 4 || !(payload.len() - 4).is_multiple_of(8) {
+            return Err(footer.invalid());
+        }
+
+        let interval = u32::from_be_bytes(payload[..4].try_into().unwrap());
+        let offsets = payload[4..]
+            .chunks_exact(8)
+            .map(|offset| u64::from_be_bytes(offset.try_into().unwrap()))
+            .collect::<Vec<u64>>();
+        if interval == 0 || offsets.iter().any(|offset| *offset >= footer.pos) {
+            return Err(footer.invalid());
+        }
+
+        // The first checksum is the header's, the last the footer's
+        let checksums = match footer.record(CHECKSUM) {
+            Some(payload) if payload.len() == (offsets.len() + 2) * 4 => {
+                let checksums = payload.chunks_exact(4).map(|crc| u32::from_be_bytes(crc.try_into().unwrap()));
+                Some(checksums.skip(1).take(offsets.len()).collect())
+            }
+            Some(_) => return Err(footer.invalid()),
+            None => None,
+        };
+
+        Ok(Some(SyncIndex {
+            format: footer.format,
+            records: footer.records,
+            interval,
+            offsets,
+            end: footer.pos,
+            checksums,
+        }))
+    }
+
+    /// Check the checksums of the `blocks` of a database held in memory, if it has them
+    pub fn check_blocks(&self, db: &[u8], blocks: Range<usize>) -> Result<(), FrError> {
+        let Some(checksums) = &self.checksums else {
+            return Ok(());
+        };
+        for block in blocks {
+            let start = self.offset(block)?;
+            let end = self.offsets.get(block + 1).copied().unwrap_or(self.end);
+            let bytes = db.get(start as usize..end as usize).ok_or(FrError::Truncated {
+                offset: db.len() as u64,
+                entry: block as u64 * self.interval as u64,
+            })?;
+            if checksums.get(block) != Some(&crc32(bytes)) {
+                return Err(FrError::BadChecksum { offset: start });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// The offset of the sync point starting `block`
+    pub fn offset(&self, block: usize) -> Result<u64, FrError> {
+        self.offsets.get(block).copied().ok_or(FrError::NoBlock {
+            block,
+            blocks: self.blocks(),
+        })
+    }
+
+    /// The block where the entries starting with `prefix` begin, assuming the database is sorted.
+    pub fn find_block<R: BufRead + Seek>(&self, reader: &mut R, prefix: &[u8]) -> Result<usize, FrError> {
+        // Number of blocks whose first entry sorts before `prefix`
+        let (mut low, mut high) = (0, self.blocks());
+        while low < high {
+            let mid = (low + high) / 2;
+            let first = FrDecompressBytes::at_sync_point(&mut *reader, self, mid)?.next().transpose()?;
+            if first.is_some_and(|first| first.as_slice() < prefix) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low.saturating_sub(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, FrDecompress},
+        std::io::Cursor,
+    };
+
+    fn sorted_db(format: Format, interval: u32) -> (Vec<String>, Vec<u8>) {
+        let dirlist = (0..1000).map(|i| format!("/usr/share/{:03}/{}", i / 10, i)).collect::<Vec<String>>();
+        let lines = Cursor::new(dirlist.join("\n"));
+        let compressed_lines = FrCompress::with_format(lines, format).sync_every(interval);
+        let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+        (dirlist, db)
+    }
+
+    #[test]
+    fn sync_points_ok() {
+        for format in [Format::LocateW, Format::Locate02] {
+            let (dirlist, db) = sorted_db(format, 64);
+
+            // The footer doesn't get in the way of a linear scan
+            let decompressed_lines = FrDecompress::new(Cursor::new(&db)).collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(dirlist, decompressed_lines);
+
+            let mut reader = Cursor::new(&db);
+            let index = SyncIndex::read(&mut reader).unwrap().unwrap();
+            assert_eq!(index.format, format);
+            assert_eq!(index.interval, 64);
+            assert_eq!(index.blocks(), 16);
+
+            for block in [0, 1, 7, 15] {
+                let decompressed_lines = FrDecompressBytes::at_sync_point(&mut reader, &index, block).unwrap();
+                let block_lines = decompressed_lines.take(64).collect::<Result<Vec<_>, _>>().unwrap();
+                let expected = dirlist.iter().skip(block * 64).take(64).map(|line| line.as_bytes().to_vec());
+                assert!(block_lines.into_iter().eq(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn find_block_ok() {
+        let (dirlist, db) = sorted_db(Format::LocateW, 64);
+        let mut reader = Cursor::new(&db);
+        let index = SyncIndex::read(&mut reader).unwrap().unwrap();
+
+        assert_eq!(index.find_block(&mut reader, b"/").unwrap(), 0);
+        assert_eq!(index.find_block(&mut reader, b"/usr/share/070").unwrap(), 10);
+        assert_eq!(index.find_block(&mut reader, b"/zzz").unwrap(), 15);
+
+        let block = index.find_block(&mut reader, b"/usr/share/050/50").unwrap();
+        let mut decompressed_lines = FrDecompressBytes::at_sync_point(&mut reader, &index, block).unwrap();
+        assert!(decompressed_lines.any(|line| line.unwrap() == dirlist[500].as_bytes()));
+    }
+
+    #[test]
+    fn no_footer_ok() {
+        let lines = Cursor::new("/usr\n/var");
+        let db = FrCompress::new(lines).collect::<Result<Vec<_>, _>>().unwrap().concat();
+        assert_eq!(SyncIndex::read(&mut Cursor::new(db)).unwrap(), None);
+
+        // Only the footer
+        let db = FrCompress::new(Cursor::new(""))
+            .sync_every(8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        let index = SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap();
+        assert_eq!(index.blocks(), 0);
+        assert_eq!(FrDecompressBytes::new(Cursor::new(&db)).count(), 0);
+    }
+}
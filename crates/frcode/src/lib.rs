@@ -1,201 +1,832 @@
+mod apply;
+mod block;
+mod checksum;
+mod diff;
+mod error;
+mod export;
+mod index;
+mod merge;
+mod metadata;
+mod mlocate;
+mod plocate;
+mod report;
+mod slice;
+mod sort;
+mod stat;
+mod trigram;
+mod writer;
+
+pub use apply::{apply, Changes};
+pub use block::DEFAULT_COMPRESSION_LEVEL;
+pub use checksum::{verify, Verified};
+pub use diff::{Change, FrDiff};
+pub use error::FrError;
+pub use export::{export, Exported, OutputMode};
+pub use index::SyncIndex;
+pub use merge::merge;
+pub use metadata::Metadata;
+pub use mlocate::MlocateReader;
+pub use plocate::PlocateReader;
+pub use report::Report;
+pub use slice::FrDecompressSlice;
+pub use sort::DEFAULT_SORT_MEMORY;
+pub use stat::{base_name, Entry, Kind, Records, Stat};
+pub use trigram::{TrigramBuilder, TrigramIndex};
+pub use writer::FrWriter;
+
+use block::{compress_block, decompress_block, COMPRESSED};
+use checksum::Crc32;
+use index::{push_control, BLOCK, CHECKSUM, CONTROL, END, INDEX, META};
+use sort::Sorter;
+use stat::{push_stat, read_stat};
+
 use std::{
-    error::Error,
+    borrow::Cow,
+    ffi::OsStr,
     fs::File,
     io,
     io::{
         prelude::{BufRead, Write},
-        BufReader, BufWriter,
+        BufReader, BufWriter, Seek, SeekFrom,
     },
     path::Path,
-    string::FromUtf8Error,
 };
 
-pub struct FrCompress<'a> {
+const LOCATEW: &[u8] = b"LOCATEW";
+const LOCATE02: &[u8] = b"LOCATE02";
+
+// Counts are a signed byte, or 0x80 followed by 2 bytes big-endian.
+// Beyond i16, the 2 bytes value i16::MIN escapes to 4 bytes big-endian.
+// The 4 bytes value i32::MIN introduces a control record (see index.rs).
+const ESCAPE_2B: u8 = 0x80;
+const ESCAPE_4B: [u8; 2] = i16::MIN.to_be_bytes();
+
+fn push_count(out_bytes: &mut Vec<u8>, count: i32) {
+    if count > -128 && count < 128 {
+        // 1 byte count
+        out_bytes.push(count as u8);
+    } else if count > i16::MIN as i32 && count <= i16::MAX as i32 {
+        // 2 bytes count big-endian
+        out_bytes.push(ESCAPE_2B);
+        out_bytes.extend_from_slice(&(count as i16).to_be_bytes());
+    } else {
+        // 4 bytes count big-endian
+        out_bytes.push(ESCAPE_2B);
+        out_bytes.extend_from_slice(&ESCAPE_4B);
+        out_bytes.extend_from_slice(&count.to_be_bytes());
+    }
+}
+
+/// Entries between two sync points written by `compress_file`
+pub const DEFAULT_SYNC_INTERVAL: u32 = 256;
+
+/// Layout of the entries following the database label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// "LOCATEW" label, each suffix is preceded by its length
+    #[default]
+    LocateW,
+    /// GNU findutils "LOCATE02" label, each suffix is NUL-terminated
+    Locate02,
+}
+
+/// Front coding state shared by `FrCompress` and `FrWriter`
+pub(crate) struct Encoder {
     init: bool,
-    prec_prefix_len: i16,
-    prec: String,
-    lines: Box<dyn Iterator<Item = io::Result<String>> + 'a>,
+    format: Format,
+    records: Records,
+    pos: u64,
+    entry: u64,
+    sync_interval: Option<u32>,
+    sync_offsets: Vec<u64>,
+    compression: Option<i32>,
+    block: Vec<u8>,
+    metadata: Option<Metadata>,
+    prec_prefix_len: usize,
+    prec: Vec<u8>,
+    prec_mtime: i64,
+    crc: Crc32,
+    checksums: Vec<u32>,
+    report: Report,
 }
 
-impl<'a> FrCompress<'a> {
-    pub fn new(reader: impl BufRead + 'a) -> FrCompress<'a> {
-        FrCompress {
+impl Encoder {
+    pub(crate) fn new(format: Format, records: Records) -> Encoder {
+        Encoder {
             init: false,
+            format,
+            records,
+            pos: 0,
+            entry: 0,
+            sync_interval: None,
+            sync_offsets: Vec::new(),
+            compression: None,
+            block: Vec::new(),
+            metadata: None,
             prec_prefix_len: 0,
-            prec: String::new(),
-            lines: Box::new(reader.lines()),
+            prec: Vec::new(),
+            prec_mtime: 0,
+            crc: Crc32::default(),
+            checksums: Vec::new(),
+            report: Report::default(),
         }
     }
-}
 
-impl<'a> Iterator for FrCompress<'a> {
-    type Item = io::Result<Vec<u8>>;
+    /// Compress the sync blocks with zstd at `level`. The Locate02 label has no room to flag it.
+    fn compress_blocks(&mut self, level: i32) {
+        if self.format == Format::LocateW {
+            self.compression = Some(level);
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.lines.next()? {
-            Ok(line) => {
-                // https://www.gnu.org/software/findutils/manual/html_node/find_html/LOCATE02-Database-Format.html
-                let mut out_bytes: Vec<u8> = vec![];
-                if !self.init {
-                    let label = b"LOCATEW";
-                    out_bytes.push(0); // offset-differential count
-                    out_bytes.push(label.len() as u8);
-                    out_bytes.extend_from_slice(label);
-                    self.init = true;
-                }
+    /// A compressed database has sync points, by default every DEFAULT_SYNC_INTERVAL entries
+    fn interval(&self) -> Option<u32> {
+        self.sync_interval.or(self.compression.map(|_| DEFAULT_SYNC_INTERVAL))
+    }
 
-                // Find the common prefix (case sensitive) between the current and the previous line
-                let mut prefix_len: usize = 0;
-                for (ch_line, ch_prec) in line.chars().zip(self.prec.chars()) {
-                    if ch_line == ch_prec {
-                        prefix_len += ch_line.len_utf8();
-                    } else {
-                        break;
-                    }
-                }
+    /// Append the BLOCK control record of the entries buffered since the last sync point
+    fn flush_block(&mut self, out_bytes: &mut Vec<u8>) -> Result<(), FrError> {
+        if let (Some(level), false) = (self.compression, self.block.is_empty()) {
+            push_control(out_bytes, BLOCK, &compress_block(&self.block, level)?);
+            self.block.clear();
+        }
+        Ok(())
+    }
 
-                // Output the offset-differential count
-                let offset: i16 = prefix_len as i16 - self.prec_prefix_len;
-                if offset > -128 && offset < 128 {
-                    // 1 byte offset
-                    out_bytes.extend_from_slice(&(offset as i8).to_be_bytes());
-                } else {
-                    out_bytes.push(0x80);
-                    // 2 bytes offset big-endian
-                    out_bytes.extend_from_slice(&offset.to_be_bytes());
-                }
+    /// The checksum of the section ending here
+    fn end_section(&mut self) {
+        self.checksums.push(self.crc.value());
+        self.crc = Crc32::default();
+    }
 
-                // Output the line without the prefix
-                let suffix_len: usize = line.len() - prefix_len;
-                if suffix_len < 128 {
-                    // 1 byte length
-                    out_bytes.extend_from_slice(&(suffix_len as i8).to_be_bytes());
-                } else {
-                    out_bytes.push(0x80);
-                    assert!(suffix_len < 32768);
-                    // 2 bytes length big-endian
-                    out_bytes.extend_from_slice(&(suffix_len as i16).to_be_bytes());
-                }
-                out_bytes.extend_from_slice(line[prefix_len..].as_bytes());
-                self.prec_prefix_len = prefix_len as i16;
-                self.prec = line;
+    fn header(&mut self, out_bytes: &mut Vec<u8>) {
+        // https://www.gnu.org/software/findutils/manual/html_node/find_html/LOCATE02-Database-Format.html
+        out_bytes.push(0); // offset-differential count
+        match (self.format, self.records) {
+            (Format::LocateW, Records::Paths) if self.compression.is_none() => {
+                out_bytes.push(LOCATEW.len() as u8);
+                out_bytes.extend_from_slice(LOCATEW);
+            }
+            (Format::LocateW, records) => {
+                // The label is followed by the layout of the records
+                out_bytes.push(LOCATEW.len() as u8 + 1);
+                out_bytes.extend_from_slice(LOCATEW);
+                let flags = if self.compression.is_some() { COMPRESSED } else { 0 };
+                out_bytes.push(records.to_byte() | flags);
+            }
+            (Format::Locate02, _) => {
+                out_bytes.extend_from_slice(LOCATE02);
+                out_bytes.push(0);
+            }
+        }
+        self.init = true;
+    }
+
+    /// Append the record of `line` to `out_bytes`
+    pub(crate) fn entry(&mut self, out_bytes: &mut Vec<u8>, line: &[u8], kind: Kind, stat: Option<Stat>) -> Result<(), FrError> {
+        if line.len() > i32::MAX as usize {
+            return Err(FrError::Oversized {
+                entry: self.entry,
+                len: line.len(),
+            });
+        }
 
-                Some(Ok(out_bytes))
+        let start = out_bytes.len();
+        if !self.init {
+            self.header(out_bytes);
+        }
+
+        // Find the common prefix (case sensitive) between the current and the previous line,
+        // unless this entry is a sync point
+        let is_sync_point = matches!(self.interval(), Some(interval) if self.entry.is_multiple_of(interval as u64));
+        let mut section_start = start;
+        let prefix_len = if is_sync_point {
+            self.flush_block(out_bytes)?; // the end of the previous section
+            self.sync_offsets.push(self.pos + (out_bytes.len() - start) as u64);
+            self.crc.update(&out_bytes[start..]);
+            self.end_section();
+            section_start = out_bytes.len();
+            0
+        } else {
+            line.iter().zip(self.prec.iter()).take_while(|(b_line, b_prec)| b_line == b_prec).count()
+        };
+
+        // Output the offset-differential count
+        let record_start = out_bytes.len();
+        push_count(out_bytes, prefix_len as i32 - self.prec_prefix_len as i32);
+        let mut counts = [out_bytes.len() - record_start, 0];
+
+        // Output the line without the prefix
+        let suffix = &line[prefix_len..];
+        match self.format {
+            Format::LocateW => {
+                let count_start = out_bytes.len();
+                push_count(out_bytes, suffix.len() as i32);
+                counts[1] = out_bytes.len() - count_start;
+                out_bytes.extend_from_slice(suffix);
+            }
+            Format::Locate02 => {
+                out_bytes.extend_from_slice(suffix);
+                out_bytes.push(0);
             }
+        }
 
-            Err(err) => Some(Err(err)),
+        // Output the metadata of the entry
+        match (self.records, stat) {
+            (Records::Stats, Some(stat)) => {
+                let prec_mtime = (self.entry > 0 && !is_sync_point).then_some(self.prec_mtime);
+                push_stat(out_bytes, &stat, prec_mtime);
+                self.prec_mtime = stat.mtime;
+            }
+            (Records::Kinds, _) => out_bytes.push(kind.to_byte()),
+            _ => (),
+        }
+        if self.compression.is_some() {
+            // The record is output with its block
+            self.block.extend_from_slice(&out_bytes[record_start..]);
+            out_bytes.truncate(record_start);
         }
+        self.crc.update(&out_bytes[section_start..]);
+        self.entry += 1;
+        self.pos += (out_bytes.len() - start) as u64;
+        self.report.entry(line, prefix_len, &counts);
+        self.report.compressed_bytes = self.pos;
+        self.prec_prefix_len = prefix_len;
+        self.prec.clear();
+        self.prec.extend_from_slice(line);
+        Ok(())
+    }
+
+    /// The footer, if the database has sync points or metadata
+    pub(crate) fn footer(&mut self) -> Result<Option<Vec<u8>>, FrError> {
+        let interval = self.interval();
+        if interval.is_none() && self.metadata.is_none() {
+            return Ok(None);
+        }
+
+        let mut out_bytes: Vec<u8> = vec![];
+        if !self.init {
+            self.header(&mut out_bytes);
+        }
+        self.flush_block(&mut out_bytes)?;
+        self.crc.update(&out_bytes);
+        self.end_section();
+
+        let footer_pos = self.pos + out_bytes.len() as u64;
+        self.report.compressed_bytes = footer_pos;
+        let records_start = out_bytes.len();
+        if let Some(interval) = interval {
+            let mut payload = Vec::with_capacity(4 + self.sync_offsets.len() * 8);
+            payload.extend_from_slice(&interval.to_be_bytes());
+            for offset in &self.sync_offsets {
+                payload.extend_from_slice(&offset.to_be_bytes());
+            }
+            push_control(&mut out_bytes, INDEX, &payload);
+        }
+        if let Some(metadata) = &mut self.metadata {
+            metadata.entries = self.entry;
+            metadata.report = Some(self.report.clone());
+            push_control(&mut out_bytes, META, &metadata.to_bytes());
+        }
+        self.crc.update(&out_bytes[records_start..]);
+        self.end_section();
+        let checksums = self.checksums.iter().flat_map(|crc| crc.to_be_bytes()).collect::<Vec<u8>>();
+        push_control(&mut out_bytes, CHECKSUM, &checksums);
+        push_control(&mut out_bytes, END, &[]);
+        index::push_trailer(&mut out_bytes, footer_pos);
+
+        self.pos += out_bytes.len() as u64;
+        Ok(Some(out_bytes))
     }
 }
 
-pub struct FrDecompress<'a> {
+/// The raw paths to compress, with their metadata in a `Records::Stats` database
+type Lines<'a> = Box<dyn Iterator<Item = io::Result<(Vec<u8>, Option<Stat>)>> + 'a>;
+
+pub struct FrCompress<'a> {
+    done: bool,
+    encoder: Encoder,
+    lines: Lines<'a>,
+    sort_memory: Option<usize>,
+}
+
+impl<'a> FrCompress<'a> {
+    pub fn new(reader: impl BufRead + 'a) -> FrCompress<'a> {
+        FrCompress::with_format(reader, Format::default())
+    }
+
+    pub fn with_format(reader: impl BufRead + 'a, format: Format) -> FrCompress<'a> {
+        FrCompress::with_separator(reader, format, b'\n')
+    }
+
+    /// Read paths separated by `separator`, e.g. NUL for the output of `find -print0`, so that they may contain newlines
+    pub fn with_separator(reader: impl BufRead + 'a, format: Format, separator: u8) -> FrCompress<'a> {
+        FrCompress::from_lines(reader.split(separator), format)
+    }
+
+    /// Compress raw paths, which don't have to be valid UTF-8
+    pub fn from_lines(lines: impl Iterator<Item = io::Result<Vec<u8>>> + 'a, format: Format) -> FrCompress<'a> {
+        let lines = lines.map(|line| line.map(|line| (line, None)));
+        FrCompress::from_records(Box::new(lines), format, Records::Paths)
+    }
+
+    /// Compress raw paths along with their metadata, in the LOCATEW format
+    pub fn from_entries(entries: impl Iterator<Item = io::Result<(Vec<u8>, Stat)>> + 'a) -> FrCompress<'a> {
+        let entries = entries.map(|entry| entry.map(|(path, stat)| (path, Some(stat))));
+        FrCompress::from_records(Box::new(entries), Format::LocateW, Records::Stats)
+    }
+
+    fn from_records(lines: Lines<'a>, format: Format, records: Records) -> FrCompress<'a> {
+        FrCompress {
+            done: false,
+            encoder: Encoder::new(format, records),
+            lines,
+            sort_memory: None,
+        }
+    }
+
+    /// Restart the front coding with a full path every `interval` entries, and end the database
+    /// with a footer indexing these sync points. GNU locate can't read this footer.
+    pub fn sync_every(mut self, interval: u32) -> FrCompress<'a> {
+        self.encoder.sync_interval = (interval > 0).then_some(interval);
+        self
+    }
+
+    /// Describe the database in its footer. The number of entries is filled in by the compressor.
+    pub fn with_metadata(mut self, metadata: Metadata) -> FrCompress<'a> {
+        self.encoder.metadata = Some(metadata);
+        self
+    }
+
+    /// Sort the lines in byte order before compressing them, see `FrWriter::sorted`.
+    /// All the lines are read when the first compressed bytes are asked for.
+    pub fn sorted(mut self, max_memory: usize) -> FrCompress<'a> {
+        self.sort_memory = Some(max_memory);
+        self
+    }
+
+    /// Compress each sync block with zstd at `level`, on top of the front coding. The blocks are still decoded
+    /// one by one, with sync points every DEFAULT_SYNC_INTERVAL entries unless `sync_every` sets them.
+    /// Only the LOCATEW format can be compressed, GNU locate can't read it anyway : a Locate02 database
+    /// is written uncompressed, `is_compressed` tells which.
+    pub fn compressed(mut self, level: i32) -> FrCompress<'a> {
+        self.encoder.compress_blocks(level);
+        self
+    }
+
+    /// Whether the sync blocks are compressed with zstd
+    pub fn is_compressed(&self) -> bool {
+        self.encoder.compression.is_some()
+    }
+
+    /// How well the entries compressed so far, complete once the footer has been returned
+    pub fn report(&self) -> &Report {
+        &self.encoder.report
+    }
+
+    fn sort_lines(&mut self, max_memory: usize) -> Result<(), FrError> {
+        let mut sorter = Sorter::new(self.encoder.records, max_memory);
+        for line in &mut self.lines {
+            let (line, stat) = line?;
+            sorter.push(&line, stat.map(|stat| stat.kind).unwrap_or_default(), stat)?;
+        }
+        self.lines = Box::new(sorter.sorted()?.map(|sorted| match sorted {
+            Ok((entry, stat)) => Ok((entry.path, stat)),
+            Err(FrError::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::other(err)),
+        }));
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for FrCompress<'a> {
+    type Item = Result<Vec<u8>, FrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(max_memory) = self.sort_memory.take() {
+            if let Err(err) = self.sort_lines(max_memory) {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+
+        let (line, stat) = match self.lines.next() {
+            Some(Ok(entry)) => entry,
+            Some(Err(err)) => return Some(Err(err.into())),
+            None => {
+                self.done = true;
+                return self.encoder.footer().transpose();
+            }
+        };
+
+        let mut out_bytes: Vec<u8> = vec![];
+        let kind = stat.map(|stat| stat.kind).unwrap_or_default();
+        Some(self.encoder.entry(&mut out_bytes, &line, kind, stat).map(|_| out_bytes))
+    }
+}
+
+/// Decompress the entries as raw paths, exactly as they were compressed
+pub struct FrDecompressBytes<'a> {
     init: bool,
-    prec_prefix_len: i16,
-    prec: String,
+    done: bool,
+    restart: bool,
+    format: Format,
+    records: Records,
+    pos: u64,
+    record_pos: u64,
+    entry: u64,
+    prec_prefix_len: usize,
+    prec: Vec<u8>,
+    stat: Option<Stat>,
+    kind: Option<Kind>,
+    compressed: bool,
+    block: Vec<u8>,
+    block_pos: usize,
+    in_block: bool,
     bytes: Box<dyn Iterator<Item = io::Result<u8>> + 'a>,
 }
 
-impl<'a> FrDecompress<'a> {
-    pub fn new(reader: impl BufRead + 'a) -> FrDecompress<'a> {
-        FrDecompress {
+impl<'a> FrDecompressBytes<'a> {
+    pub fn new(reader: impl BufRead + 'a) -> FrDecompressBytes<'a> {
+        FrDecompressBytes {
             init: false,
+            done: false,
+            restart: false,
+            format: Format::default(),
+            records: Records::default(),
+            pos: 0,
+            record_pos: 0,
+            entry: 0,
             prec_prefix_len: 0,
-            prec: String::with_capacity(1_000),
+            prec: Vec::with_capacity(1_000),
+            stat: None,
+            kind: None,
+            compressed: false,
+            block: Vec::new(),
+            block_pos: 0,
+            in_block: false,
             bytes: Box::new(reader.bytes()),
         }
     }
 
-    fn count_from_bytes(&mut self) -> Option<i16> {
-        let bytes_mut = &mut self.bytes;
-        let count_1b = bytes_mut.take(1).filter_map(Result::ok).collect::<Vec<u8>>();
-        if count_1b.len() != 1 {
-            None
-        } else if count_1b[0] != 0x80 {
-            Some(i8::from_be_bytes([count_1b[0]]) as i16)
+    /// Decompress from the sync point starting `block`, up to the end of the database
+    pub fn at_sync_point<R: BufRead + Seek + 'a>(mut reader: R, index: &SyncIndex, block: usize) -> Result<FrDecompressBytes<'a>, FrError> {
+        let offset = index.offset(block)?;
+        reader.seek(SeekFrom::Start(offset))?;
+
+        let mut decompressed_lines = FrDecompressBytes::new(reader);
+        decompressed_lines.init = true;
+        decompressed_lines.restart = true;
+        decompressed_lines.format = index.format;
+        decompressed_lines.records = index.records;
+        decompressed_lines.pos = offset;
+        decompressed_lines.entry = block as u64 * index.interval as u64;
+        Ok(decompressed_lines)
+    }
+
+    /// The format of the database, known once the label has been read
+    pub fn format(&self) -> Option<Format> {
+        self.init.then_some(self.format)
+    }
+
+    /// What the records store besides the path, known once the label has been read
+    pub fn records(&self) -> Option<Records> {
+        self.init.then_some(self.records)
+    }
+
+    /// The metadata of the last entry returned, if the database stores it
+    pub fn stat(&self) -> Option<Stat> {
+        self.stat
+    }
+
+    /// The kind of the last entry returned, if the database stores it
+    pub fn kind(&self) -> Option<Kind> {
+        self.kind
+    }
+
+    /// Whether the sync blocks are compressed, known once the label has been read
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// The remaining entries along with their kind
+    pub fn entries(self) -> impl Iterator<Item = Result<Entry, FrError>> + 'a {
+        let mut decompressed_lines = self;
+        std::iter::from_fn(move || {
+            let entry = decompressed_lines.next()?.map(|path| Entry {
+                path,
+                kind: decompressed_lines.kind(),
+            });
+            Some(entry)
+        })
+    }
+
+    /// Byte offset of the last entry returned, or of its block in a compressed database
+    pub fn offset(&self) -> u64 {
+        self.record_pos
+    }
+
+    /// Length of the prefix the last entry returned shares with the previous one
+    pub fn prefix_len(&self) -> usize {
+        self.prec_prefix_len
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, FrError> {
+        if self.in_block {
+            let byte = self.block.get(self.block_pos).copied();
+            self.block_pos += byte.is_some() as usize;
+            return Ok(byte);
+        }
+
+        match self.bytes.next() {
+            Some(Ok(byte)) => {
+                self.pos += 1;
+                Ok(Some(byte))
+            }
+            Some(Err(err)) => Err(err.into()),
+            None => Ok(None),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, FrError> {
+        self.next_byte()?.ok_or(FrError::Truncated {
+            offset: self.pos,
+            entry: self.entry,
+        })
+    }
+
+    fn count_from_byte(&mut self, count_1b: u8) -> Result<i32, FrError> {
+        if count_1b != ESCAPE_2B {
+            return Ok(i8::from_be_bytes([count_1b]) as i32);
+        }
+
+        let count_2b = [self.byte()?, self.byte()?];
+        if count_2b != ESCAPE_4B {
+            Ok(i16::from_be_bytes(count_2b) as i32)
         } else {
-            let count_2b = bytes_mut.take(2).filter_map(Result::ok).collect::<Vec<u8>>();
-            assert_eq!(count_2b.len(), 2);
-            Some(i16::from_be_bytes([count_2b[0], count_2b[1]]))
+            Ok(i32::from_be_bytes([self.byte()?, self.byte()?, self.byte()?, self.byte()?]))
         }
     }
 
-    fn suffix_from_bytes(&mut self, len: usize) -> Result<String, FromUtf8Error> {
-        let bytes_mut = &mut self.bytes;
-        let suffix = bytes_mut.take(len).filter_map(Result::ok).collect::<Vec<u8>>();
-        assert_eq!(suffix.len(), len);
-        String::from_utf8(suffix)
+    fn suffix_from_bytes(&mut self, len: usize, line: &mut Vec<u8>) -> Result<(), FrError> {
+        for _ in 0..len {
+            line.push(self.byte()?);
+        }
+        Ok(())
     }
-}
 
-impl<'a> Iterator for FrDecompress<'a> {
-    type Item = Result<String, Box<dyn Error>>;
+    fn suffix_until_nul(&mut self, line: &mut Vec<u8>) -> Result<(), FrError> {
+        loop {
+            match self.byte()? {
+                0 => return Ok(()),
+                byte => line.push(byte),
+            }
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if !self.init {
-            let _ = self.bytes.next()?; // Skip the offset
-            let len = self.count_from_bytes()?;
-            let label = match self.suffix_from_bytes(len as usize) {
-                Ok(label) => label,
-                Err(err) => return Some(Err(err.into())),
+    fn invalid_offset(&self) -> FrError {
+        FrError::InvalidOffset {
+            offset: self.record_pos,
+            entry: self.entry,
+        }
+    }
+
+    pub(crate) fn read_header(&mut self) -> Result<bool, FrError> {
+        if self.next_byte()?.is_none() {
+            return Ok(false); // empty database
+        }
+
+        // LOCATEW stores the label length, LOCATE02 starts the label right away
+        let mut label = Vec::new();
+        let first = self.byte().map_err(|_| FrError::BadHeader)?;
+        let expected = if first == LOCATEW.len() as u8 || first == LOCATEW.len() as u8 + 1 {
+            self.format = Format::LocateW;
+            self.suffix_from_bytes(LOCATEW.len(), &mut label).map_err(|_| FrError::BadHeader)?;
+            if first > LOCATEW.len() as u8 {
+                let records = self.byte().map_err(|_| FrError::BadHeader)?;
+                self.records = Records::from_byte(records & !COMPRESSED).ok_or(FrError::BadHeader)?;
+                self.compressed = records & COMPRESSED != 0;
+            }
+            LOCATEW
+        } else {
+            self.format = Format::Locate02;
+            label.push(first);
+            // Don't scan the whole file for a NUL if this isn't a database
+            while label.len() <= LOCATE02.len() {
+                match self.byte().map_err(|_| FrError::BadHeader)? {
+                    0 => break,
+                    byte => label.push(byte),
+                }
+            }
+            LOCATE02
+        };
+
+        if label != expected {
+            return Err(FrError::BadHeader);
+        }
+        self.init = true;
+        Ok(true)
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Vec<u8>>, FrError> {
+        if !self.init && !self.read_header()? {
+            return Ok(None);
+        }
+
+        let offset = loop {
+            if self.in_block && self.block_pos == self.block.len() {
+                self.in_block = false;
+            }
+            if !self.in_block {
+                self.record_pos = self.pos;
+            }
+            let Some(count_1b) = self.next_byte()? else {
+                return Ok(None); // end of valid updateDB file happens here
             };
+            let offset = self.count_from_byte(count_1b)?;
+            if offset != CONTROL {
+                break offset;
+            }
+            if self.in_block {
+                return Err(self.invalid_offset());
+            }
 
-            if label == "LOCATEW" {
-                self.init = true;
-            } else {
-                return Some(Err("Fichier updateDB invalide".into()));
+            let tag = self.byte()?;
+            let len = u32::from_be_bytes([self.byte()?, self.byte()?, self.byte()?, self.byte()?]);
+            match tag {
+                END => return Ok(None), // the footer follows
+                BLOCK => {
+                    // Grown as the bytes are read, the length may be rotten
+                    let mut payload = Vec::new();
+                    for _ in 0..len {
+                        payload.push(self.byte()?);
+                    }
+                    decompress_block(&payload, &mut self.block).map_err(|_| self.invalid_offset())?;
+                    self.block_pos = 0;
+                    self.in_block = true;
+                    self.restart = true; // each block starts with a sync point
+                }
+                _ => {
+                    for _ in 0..len {
+                        self.byte()?; // not needed to decompress the entries
+                    }
+                }
             }
+        };
+
+        // A sync point doesn't depend on the previous entry
+        let prefix_len = if self.restart {
+            self.restart = false;
+            0
+        } else {
+            self.prec_prefix_len as i64 + offset as i64
+        };
+        if prefix_len < 0 || prefix_len as usize > self.prec.len() {
+            return Err(self.invalid_offset());
         }
+        let prefix_len = prefix_len as usize;
 
-        let offset = self.count_from_bytes()?; // end of valid updateDB file happens here
-        let suffix_len = self.count_from_bytes()?;
-        let suffix = match self.suffix_from_bytes(suffix_len as usize) {
-            Ok(suffix) => suffix,
-            Err(err) => return Some(Err(err.into())),
+        let mut line = Vec::with_capacity(self.prec.len() + 64);
+        line.extend_from_slice(&self.prec[..prefix_len]);
+        match self.format {
+            Format::LocateW => {
+                let count_1b = self.byte()?;
+                let suffix_len = self.count_from_byte(count_1b)?;
+                if suffix_len < 0 {
+                    return Err(self.invalid_offset());
+                }
+                self.suffix_from_bytes(suffix_len as usize, &mut line)?
+            }
+            Format::Locate02 => self.suffix_until_nul(&mut line)?,
         };
 
-        let prefix_len = self.prec_prefix_len + offset;
-        let mut line = String::with_capacity((prefix_len + suffix_len) as usize);
-        line.push_str(&self.prec[..prefix_len as usize]);
-        line.push_str(&suffix);
+        match self.records {
+            Records::Stats => {
+                let (offset, entry) = (self.record_pos, self.entry);
+                let prec_mtime = self.stat.map_or(0, |stat| stat.mtime);
+                let invalid = move || FrError::InvalidOffset { offset, entry };
+                let stat = read_stat(|| self.byte(), invalid, prec_mtime)?;
+                self.stat = Some(stat);
+                self.kind = Some(stat.kind);
+            }
+            Records::Kinds => {
+                let kind_byte = self.byte()?;
+                self.kind = Some(Kind::from_byte(kind_byte).ok_or_else(|| self.invalid_offset())?);
+            }
+            Records::Paths => (),
+        }
 
+        self.entry += 1;
         self.prec_prefix_len = prefix_len;
         self.prec.clear();
-        self.prec.push_str(&line);
+        self.prec.extend_from_slice(&line);
 
-        Some(Ok(line))
+        Ok(Some(line))
     }
 }
 
-pub fn compress_file(in_file: &Path, out_file: &Path) -> Result<usize, Box<dyn Error>> {
-    let reader = BufReader::new(File::open(in_file)?);
-    let compressed_lines = FrCompress::new(reader);
+impl<'a> Iterator for FrDecompressBytes<'a> {
+    type Item = Result<Vec<u8>, FrError>;
 
-    let mut writer = BufWriter::new(File::create(out_file)?);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_entry() {
+            Ok(Some(line)) => Some(Ok(line)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true; // can't resynchronize after a bad entry
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Decompress the entries as UTF-8 paths
+pub struct FrDecompress<'a> {
+    bytes: FrDecompressBytes<'a>,
+}
+
+impl<'a> FrDecompress<'a> {
+    pub fn new(reader: impl BufRead + 'a) -> FrDecompress<'a> {
+        FrDecompress {
+            bytes: FrDecompressBytes::new(reader),
+        }
+    }
+
+    /// The format of the database, known once the label has been read
+    pub fn format(&self) -> Option<Format> {
+        self.bytes.format()
+    }
+
+    /// The metadata of the last entry returned, if the database stores it
+    pub fn stat(&self) -> Option<Stat> {
+        self.bytes.stat()
+    }
+}
+
+impl<'a> Iterator for FrDecompress<'a> {
+    type Item = Result<String, FrError>;
 
-    let mut ctr_bytes: usize = 0;
-    for line in compressed_lines {
-        let line = line?;
-        writer.write_all(&line)?;
-        ctr_bytes += line.len();
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.bytes.next()? {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+
+        Some(String::from_utf8(line).map_err(|_| FrError::InvalidUtf8 {
+            offset: self.bytes.record_pos,
+            entry: self.bytes.entry - 1,
+        }))
     }
+}
 
-    Ok(ctr_bytes)
+/// View a raw path as an `OsStr`. Exact on Unix, lossy elsewhere for non UTF-8 paths.
+pub fn to_os_str(bytes: &[u8]) -> Cow<'_, OsStr> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        match String::from_utf8_lossy(bytes) {
+            Cow::Borrowed(s) => Cow::Borrowed(OsStr::new(s)),
+            Cow::Owned(s) => Cow::Owned(s.into()),
+        }
+    }
 }
 
-pub fn decompress_file(in_file: &Path, out_file: &Path) -> Result<usize, Box<dyn Error>> {
+/// Compress a list of paths, one per line, with sync points. Returns how well they compressed.
+pub fn compress_file(in_file: &Path, out_file: &Path, metadata: Option<Metadata>) -> Result<Report, FrError> {
     let reader = BufReader::new(File::open(in_file)?);
-    let decompressed_lines = FrDecompress::new(reader);
+    let mut compressed_lines = FrCompress::new(reader).sync_every(DEFAULT_SYNC_INTERVAL);
+    if let Some(metadata) = metadata {
+        compressed_lines = compressed_lines.with_metadata(metadata);
+    }
 
     let mut writer = BufWriter::new(File::create(out_file)?);
-
-    let mut ctr_bytes: usize = 0;
-    for line in decompressed_lines {
-        let line = line?;
-        writer.write_all(line.as_bytes())?;
-        ctr_bytes += line.len();
+    for line in compressed_lines.by_ref() {
+        writer.write_all(&line?)?;
     }
+    writer.flush()?;
 
-    Ok(ctr_bytes)
+    Ok(compressed_lines.report().clone())
+}
+
+/// Decompress `in_file` into `out_file`, one entry per line, NUL-terminated or as JSON lines
+pub fn decompress_file(in_file: &Path, out_file: &Path, mode: OutputMode) -> Result<Exported, FrError> {
+    let reader = BufReader::new(File::open(in_file)?);
+    let writer = BufWriter::new(File::create(out_file)?);
+    export(reader, writer, mode)
 }
 
 #[cfg(test)]
@@ -237,4 +868,200 @@ mod tests {
             assert_eq!(before, after);
         }
     }
+
+    #[test]
+    fn nul_separated_ok() {
+        let dirlist = ["/tmp", "/tmp/a\nb", "/tmp/a\nb/c\r\n", "/tmp/d"];
+
+        for format in [Format::LocateW, Format::Locate02] {
+            let lines = Cursor::new(dirlist.join("\0") + "\0");
+            let compressed = FrCompress::with_separator(lines, format, b'\0').collect::<Result<Vec<_>, _>>().unwrap();
+            let decompressed_lines = FrDecompress::new(Cursor::new(compressed.concat()));
+            assert_eq!(decompressed_lines.collect::<Result<Vec<_>, _>>().unwrap(), dirlist);
+        }
+    }
+
+    #[test]
+    fn locate02_gnu_layout() {
+        let dirlist = ["/usr/src", "/usr/src/cmd/aardvark.c", "/usr/src/cmd/armadillo.c", "/usr/tmp/zoo"];
+
+        let lines = Cursor::new(dirlist.join("\n"));
+        let compressed = FrCompress::with_format(lines, Format::Locate02)
+            .filter_map(Result::ok)
+            .flatten()
+            .collect::<Vec<u8>>();
+
+        // Example from the findutils manual
+        let mut expected: Vec<u8> = b"\0LOCATE02\0".to_vec();
+        expected.extend_from_slice(b"\0/usr/src\0");
+        expected.extend_from_slice(b"\x08/cmd/aardvark.c\0");
+        expected.extend_from_slice(b"\x06rmadillo.c\0");
+        expected.extend_from_slice(b"\xf7tmp/zoo\0");
+        assert_eq!(compressed, expected);
+
+        let mut decompressed_lines = FrDecompress::new(Cursor::new(compressed));
+        for before in dirlist {
+            assert_eq!(before, decompressed_lines.next().unwrap().unwrap());
+        }
+        assert_eq!(decompressed_lines.format(), Some(Format::Locate02));
+        assert!(decompressed_lines.next().is_none());
+    }
+
+    #[test]
+    fn bad_label_err() {
+        let mut decompressed_lines = FrDecompress::new(Cursor::new(b"\0LOCATE03\0\0/usr\0".to_vec()));
+        assert!(matches!(decompressed_lines.next(), Some(Err(FrError::BadHeader))));
+        assert!(decompressed_lines.next().is_none());
+
+        let mut decompressed_lines = FrDecompress::new(Cursor::new(b"\0LOCATEW".to_vec()));
+        assert!(matches!(decompressed_lines.next(), Some(Err(FrError::BadHeader))));
+    }
+
+    #[test]
+    fn corrupt_entries_err() {
+        let header = b"\0\x07LOCATEW".to_vec();
+
+        // The second entry announces 5 bytes but only 2 are left
+        let mut db = header.clone();
+        db.extend_from_slice(b"\0\x04/usr\x04\x05/b");
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(db));
+        assert_eq!(decompressed_lines.next().unwrap().unwrap(), b"/usr");
+        assert!(matches!(
+            decompressed_lines.next(),
+            Some(Err(FrError::Truncated { offset: 19, entry: 1 }))
+        ));
+        assert!(decompressed_lines.next().is_none());
+
+        // The second entry shares more bytes than the first one has
+        let mut db = header.clone();
+        db.extend_from_slice(b"\0\x04/usr\x05\x01b");
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(db));
+        assert!(decompressed_lines.next().unwrap().is_ok());
+        assert!(matches!(
+            decompressed_lines.next(),
+            Some(Err(FrError::InvalidOffset { offset: 15, entry: 1 }))
+        ));
+
+        // Truncated in the middle of a 2 bytes count
+        let mut db = header;
+        db.extend_from_slice(b"\0\x80\x01");
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(db));
+        assert!(matches!(
+            decompressed_lines.next(),
+            Some(Err(FrError::Truncated { offset: 12, entry: 0 }))
+        ));
+    }
+
+    #[test]
+    fn io_err() {
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disque"))
+            }
+        }
+
+        let mut decompressed_lines = FrDecompressBytes::new(BufReader::new(Failing));
+        assert!(matches!(decompressed_lines.next(), Some(Err(FrError::Io(_)))));
+        let mut compressed_lines = FrCompress::new(BufReader::new(Failing));
+        assert!(matches!(compressed_lines.next(), Some(Err(FrError::Io(_)))));
+    }
+
+    #[test]
+    fn long_entries_ok() {
+        let long_dir = format!("/{}", "a".repeat(40_000));
+        let dirlist = vec![
+            "/usr".to_owned(),
+            format!("{long_dir}/{}", "b".repeat(100_000)),
+            format!("{long_dir}/c"),
+            format!("{long_dir}/{}", "d".repeat(200)),
+            "/var".to_owned(), // prefix delta beyond i16
+        ];
+
+        for format in [Format::LocateW, Format::Locate02] {
+            let lines = Cursor::new(dirlist.join("\n"));
+            let compressed_lines = FrCompress::with_format(lines, format).collect::<Result<Vec<_>, _>>().unwrap();
+            let lines = Cursor::new(compressed_lines.into_iter().flatten().collect::<Vec<u8>>());
+            let decompressed_lines = FrDecompress::new(lines).collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(dirlist, decompressed_lines);
+        }
+    }
+
+    #[test]
+    fn counts_ok() {
+        for count in [0, 1, -1, 127, -127, 128, -128, 32767, -32767, 32768, -32768, i32::MAX, -i32::MAX] {
+            let mut db = b"\0\x07LOCATEW".to_vec();
+            push_count(&mut db, count);
+            let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(db));
+            decompressed_lines.read_header().unwrap();
+            let count_1b = decompressed_lines.byte().unwrap();
+            assert_eq!(decompressed_lines.count_from_byte(count_1b).unwrap(), count);
+            assert!(decompressed_lines.next_byte().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn compress_decompress_bytes_ok() {
+        let dirlist: Vec<Vec<u8>> = vec![
+            b"/home/fourmilier".to_vec(),
+            b"/home/fourmilier/b\xe9b\xe9.jpg".to_vec(), // Latin-1
+            b"/home/fourmilier/b\xe9b\xe9\xff.jpg".to_vec(),
+            "/home/fourmilier/è".as_bytes().to_vec(),
+            "/home/fourmilier/é".as_bytes().to_vec(), // the common prefix ends inside a char
+        ];
+
+        for format in [Format::LocateW, Format::Locate02] {
+            let compressed_lines = FrCompress::from_lines(dirlist.clone().into_iter().map(Ok), format);
+            let lines = Cursor::new(compressed_lines.filter_map(Result::ok).flatten().collect::<Vec<u8>>());
+            let decompressed_lines = FrDecompressBytes::new(lines).filter_map(Result::ok).collect::<Vec<Vec<u8>>>();
+            assert_eq!(dirlist, decompressed_lines);
+        }
+
+        let compressed_lines = FrCompress::from_lines(dirlist.clone().into_iter().map(Ok), Format::LocateW);
+        let lines = Cursor::new(compressed_lines.filter_map(Result::ok).flatten().collect::<Vec<u8>>());
+        let mut decompressed_lines = FrDecompress::new(lines);
+        assert!(decompressed_lines.next().unwrap().is_ok());
+        assert!(decompressed_lines.next().unwrap().is_err());
+        assert!(decompressed_lines.next().unwrap().is_err());
+        assert_eq!(decompressed_lines.next().unwrap().unwrap(), "/home/fourmilier/è");
+        assert_eq!(decompressed_lines.next().unwrap().unwrap(), "/home/fourmilier/é");
+    }
+
+    #[test]
+    fn stats_ok() {
+        let entries = (0..100)
+            .map(|i| {
+                let kind = if i % 10 == 0 { Kind::Dir } else { Kind::File };
+                let stat = Stat {
+                    kind,
+                    size: i * 1000,
+                    mtime: 1_700_000_000 - (i as i64 % 7) * 86_400,
+                };
+                (format!("/srv/{:02}/{}", i / 10, i).into_bytes(), stat)
+            })
+            .collect::<Vec<_>>();
+        let compressed_lines = FrCompress::from_entries(entries.clone().into_iter().map(Ok)).sync_every(16);
+        let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(&db));
+        for (path, stat) in &entries {
+            assert_eq!(decompressed_lines.next().unwrap().unwrap(), *path);
+            assert_eq!(decompressed_lines.stat(), Some(*stat));
+        }
+        assert!(decompressed_lines.next().is_none());
+        assert_eq!(decompressed_lines.records(), Some(Records::Stats));
+
+        let index = SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap();
+        assert_eq!(index.records, Records::Stats);
+        let mut decompressed_lines = FrDecompressSlice::at_sync_point(&db, &index, 3).unwrap();
+        for (path, stat) in &entries[48..] {
+            assert_eq!(decompressed_lines.next_entry().unwrap().unwrap(), path.as_slice());
+            assert_eq!(decompressed_lines.stat(), Some(*stat));
+        }
+
+        // Paths only databases don't have any
+        let mut decompressed_lines = FrDecompress::new(Cursor::new("\0\x07LOCATEW\0\x04/usr"));
+        assert_eq!(decompressed_lines.next().unwrap().unwrap(), "/usr");
+        assert_eq!(decompressed_lines.stat(), None);
+    }
 }
@@ -0,0 +1,231 @@
+use {
+    crate::{Entry, FrDecompressBytes, FrError, FrWriter, Kind, Metadata, Records, Stat, DEFAULT_SYNC_INTERVAL},
+    std::cmp::{Ordering, Reverse},
+    std::collections::BinaryHeap,
+    std::io::{BufRead, Write},
+};
+
+/// The next entry of an input
+struct Head {
+    path: Vec<u8>,
+    kind: Option<Kind>,
+    stat: Option<Stat>,
+    input: usize,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.path, self.input).cmp(&(&other.path, other.input))
+    }
+}
+
+fn next_head(decompressed_lines: &mut FrDecompressBytes, input: usize) -> Result<Option<Reverse<Head>>, FrError> {
+    let Some(path) = decompressed_lines.next().transpose()? else {
+        return Ok(None);
+    };
+    let (kind, stat) = (decompressed_lines.kind(), decompressed_lines.stat());
+    Ok(Some(Reverse(Head { path, kind, stat, input })))
+}
+
+/// The entries of sorted inputs in byte order, each path once
+pub(crate) struct Merged<'a> {
+    inputs: Vec<FrDecompressBytes<'a>>,
+    heads: BinaryHeap<Reverse<Head>>,
+    prec: Option<Vec<u8>>,
+}
+
+impl<'a> Merged<'a> {
+    pub(crate) fn new(mut inputs: Vec<FrDecompressBytes<'a>>) -> Result<Merged<'a>, FrError> {
+        let mut heads = BinaryHeap::with_capacity(inputs.len());
+        for (input, decompressed_lines) in inputs.iter_mut().enumerate() {
+            heads.extend(next_head(decompressed_lines, input)?);
+        }
+        Ok(Merged { inputs, heads, prec: None })
+    }
+
+    /// The records of the inputs, known once their first entry has been read. None for an empty input.
+    fn records(&self) -> impl Iterator<Item = Records> + '_ {
+        self.inputs.iter().filter_map(FrDecompressBytes::records)
+    }
+}
+
+impl Iterator for Merged<'_> {
+    type Item = Result<(Entry, Option<Stat>), FrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Reverse(head)) = self.heads.pop() {
+            match next_head(&mut self.inputs[head.input], head.input) {
+                Ok(next) => self.heads.extend(next),
+                Err(err) => {
+                    self.heads.clear();
+                    return Some(Err(err));
+                }
+            }
+            if self.prec.as_ref() == Some(&head.path) {
+                continue; // already returned from a previous input
+            }
+
+            self.prec = Some(head.path.clone());
+            let entry = Entry {
+                path: head.path,
+                kind: head.kind,
+            };
+            return Some(Ok((entry, head.stat)));
+        }
+        None
+    }
+}
+
+/// Merge databases sorted in byte order into one, with sync points. A path found in several inputs is kept once,
+/// with the metadata of the first input having it. The metadata (or else the kind) of each entry is kept if all inputs have it.
+/// Unsorted inputs are merged all the same, but their duplicates may be kept.
+pub fn merge<'a, W: Write>(inputs: Vec<impl BufRead + 'a>, writer: W, metadata: Option<Metadata>) -> Result<u64, FrError> {
+    let merged = Merged::new(inputs.into_iter().map(FrDecompressBytes::new).collect())?;
+
+    let is_stats = merged.records().all(|records| records == Records::Stats);
+    let is_kinds = merged.records().all(|records| records != Records::Paths);
+    let writer = if is_stats {
+        FrWriter::with_stats(writer)
+    } else if is_kinds {
+        FrWriter::with_kinds(writer)
+    } else {
+        FrWriter::new(writer)
+    };
+    let mut writer = writer.sync_every(DEFAULT_SYNC_INTERVAL);
+    if let Some(metadata) = metadata {
+        writer = writer.with_metadata(metadata);
+    }
+
+    let mut entries = 0;
+    for merged_entry in merged {
+        match merged_entry? {
+            (entry, Some(stat)) if is_stats => writer.push_stat(&entry.path, stat)?,
+            (entry, _) => writer.push(&entry.path, entry.kind.unwrap_or_default())?,
+        }
+        entries += 1;
+    }
+
+    writer.finish()?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, FrDecompress},
+        std::io::Cursor,
+    };
+
+    fn db(lines: &[&str]) -> Vec<u8> {
+        let compressed_lines = FrCompress::new(Cursor::new(lines.join("\n"))).sync_every(2);
+        compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat()
+    }
+
+    #[test]
+    fn merge_ok() {
+        let dbs = [
+            db(&["C:\\", "C:\\b", "C:\\d", "D:\\a"]),
+            db(&["C:\\a", "C:\\b", "C:\\c"]),
+            db(&[]),
+            db(&["C:\\", "E:\\"]),
+        ];
+        let mut merged = Vec::new();
+        let metadata = Metadata {
+            tool: "merge".to_owned(),
+            ..Default::default()
+        };
+        let entries = merge(dbs.iter().map(Cursor::new).collect(), &mut merged, Some(metadata)).unwrap();
+        assert_eq!(entries, 7);
+
+        let decompressed_lines = FrDecompress::new(Cursor::new(&merged)).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(decompressed_lines, ["C:\\", "C:\\a", "C:\\b", "C:\\c", "C:\\d", "D:\\a", "E:\\"]);
+        let metadata = Metadata::read(&mut Cursor::new(&merged)).unwrap().unwrap();
+        assert_eq!((metadata.entries, metadata.dirs, metadata.files), (7, 0, 7));
+    }
+
+    #[test]
+    fn merge_kinds_ok() {
+        let kinds_db = |entries: &[(&str, Kind)]| {
+            let mut writer = FrWriter::with_kinds(Vec::new());
+            for (path, kind) in entries {
+                writer.push(path.as_bytes(), *kind).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let stat = Stat {
+            kind: Kind::Symlink,
+            ..Default::default()
+        };
+        let stats_db = FrCompress::from_entries([Ok((b"/c".to_vec(), stat))].into_iter())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        let dbs = [kinds_db(&[("/a", Kind::Dir), ("/a/b", Kind::File)]), stats_db, Vec::new()];
+        let mut merged = Vec::new();
+        merge(dbs.iter().map(Cursor::new).collect(), &mut merged, Some(Metadata::default())).unwrap();
+
+        let entries = FrDecompressBytes::new(Cursor::new(&merged))
+            .entries()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let kinds = entries.iter().map(|entry| entry.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [Some(Kind::Dir), Some(Kind::File), Some(Kind::Symlink)]);
+        let metadata = Metadata::read(&mut Cursor::new(&merged)).unwrap().unwrap();
+        assert_eq!((metadata.dirs, metadata.files), (1, 2));
+    }
+
+    #[test]
+    fn merge_stats_ok() {
+        let stats_db = |entries: &[(&str, i64)]| {
+            let entries = entries.iter().map(|(path, mtime)| {
+                let stat = Stat {
+                    mtime: *mtime,
+                    ..Default::default()
+                };
+                Ok((path.as_bytes().to_vec(), stat))
+            });
+            FrCompress::from_entries(entries).collect::<Result<Vec<_>, _>>().unwrap().concat()
+        };
+        let dbs = [stats_db(&[("/a", 1), ("/c", 3)]), stats_db(&[("/a", 10), ("/b", 2)])];
+        let mut merged = Vec::new();
+        merge(dbs.iter().map(Cursor::new).collect(), &mut merged, None).unwrap();
+
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(&merged));
+        for (path, mtime) in [("/a", 1), ("/b", 2), ("/c", 3)] {
+            assert_eq!(decompressed_lines.next().unwrap().unwrap(), path.as_bytes());
+            assert_eq!(decompressed_lines.stat().unwrap().mtime, mtime);
+        }
+
+        // Without stats as soon as one input doesn't have them
+        let dbs = [stats_db(&[("/a", 1)]), db(&["/b"])];
+        let mut merged = Vec::new();
+        merge(dbs.iter().map(Cursor::new).collect(), &mut merged, None).unwrap();
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(&merged));
+        assert_eq!(decompressed_lines.next().unwrap().unwrap(), b"/a");
+        assert_eq!(decompressed_lines.stat(), None);
+    }
+
+    #[test]
+    fn merge_err() {
+        let dbs = [db(&["/a"]), b"\0\x07LOCATEX".to_vec()];
+        assert!(matches!(
+            merge(dbs.iter().map(Cursor::new).collect(), Vec::new(), None),
+            Err(FrError::BadHeader)
+        ));
+    }
+}
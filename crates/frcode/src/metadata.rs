@@ -0,0 +1,240 @@
+// META control record payload : the version, then the fields of version 1 in order.
+// Version 2 appends the compression report : raw bytes, compressed bytes, 2 bytes escapes, 4 bytes escapes,
+// prefix bytes, the longest path as raw bytes, then the prefix lengths histogram as a list of at most
+// MAX_BUCKETS numbers.
+// Numbers are 8 bytes big-endian, strings and raw bytes are a 4 bytes length then the bytes (UTF-8 for strings),
+// lists are a 4 bytes count then their items.
+// A new version may only append fields, so older readers ignore what follows the fields they know.
+
+use {
+    crate::{
+        index::{read_footer, META},
+        FrError, Report,
+    },
+    std::io::{Read, Seek},
+};
+
+const VERSION: u8 = 2;
+/// One bucket per power of 2 of a 64 bits length, and one for no prefix
+const MAX_BUCKETS: usize = 65;
+
+/// Description of the database stored in its footer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    /// Written by the compressor
+    pub entries: u64,
+    pub dirs: u64,
+    pub files: u64,
+    pub files_bytes: u64,
+    /// Seconds taken to generate the database
+    pub elapsed: u64,
+    /// Name and version of the program that generated the database
+    pub tool: String,
+    pub roots: Vec<String>,
+    pub options: Vec<String>,
+    /// Filled in by the compressor, `None` in a database written before version 2
+    pub report: Option<Report>,
+}
+
+impl Metadata {
+    /// Read the metadata from the footer of a database. `None` if it was written without.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Option<Metadata>, FrError> {
+        let Some(footer) = read_footer(reader)? else {
+            return Ok(None);
+        };
+        let Some(payload) = footer.record(META) else {
+            return Ok(None);
+        };
+        Metadata::from_bytes(payload).map(Some).ok_or_else(|| footer.invalid())
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        fn push_str(payload: &mut Vec<u8>, s: &[u8]) {
+            payload.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            payload.extend_from_slice(s);
+        }
+
+        // Without report, the fields of version 1 are enough
+        let mut payload = vec![if self.report.is_some() { VERSION } else { 1 }];
+        for n in [self.created, self.entries, self.dirs, self.files, self.files_bytes, self.elapsed] {
+            payload.extend_from_slice(&n.to_be_bytes());
+        }
+        push_str(&mut payload, self.tool.as_bytes());
+        for list in [&self.roots, &self.options] {
+            payload.extend_from_slice(&(list.len() as u32).to_be_bytes());
+            for s in list {
+                push_str(&mut payload, s.as_bytes());
+            }
+        }
+
+        if let Some(report) = &self.report {
+            for n in [
+                report.raw_bytes,
+                report.compressed_bytes,
+                report.escapes_2b,
+                report.escapes_4b,
+                report.prefix_bytes,
+            ] {
+                payload.extend_from_slice(&n.to_be_bytes());
+            }
+            push_str(&mut payload, &report.longest);
+            payload.extend_from_slice(&(report.prefix_lengths.len() as u32).to_be_bytes());
+            for n in &report.prefix_lengths {
+                payload.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+        payload
+    }
+
+    fn from_bytes(payload: &[u8]) -> Option<Metadata> {
+        let mut fields = Fields(payload);
+        let version = fields.take(1)?[0];
+        if version < 1 {
+            return None;
+        }
+
+        let mut metadata = Metadata {
+            created: fields.number()?,
+            entries: fields.number()?,
+            dirs: fields.number()?,
+            files: fields.number()?,
+            files_bytes: fields.number()?,
+            elapsed: fields.number()?,
+            tool: fields.string()?,
+            roots: fields.list()?,
+            options: fields.list()?,
+            report: None,
+        };
+        if version >= 2 {
+            metadata.report = Some(Report {
+                entries: metadata.entries,
+                raw_bytes: fields.number()?,
+                compressed_bytes: fields.number()?,
+                escapes_2b: fields.number()?,
+                escapes_4b: fields.number()?,
+                prefix_bytes: fields.number()?,
+                longest: fields.bytes()?.to_vec(),
+                prefix_lengths: {
+                    let buckets = fields.len()?;
+                    if buckets > MAX_BUCKETS {
+                        return None;
+                    }
+                    (0..buckets).map(|_| fields.number()).collect::<Option<_>>()?
+                },
+            });
+        }
+        Some(metadata)
+    }
+}
+
+struct Fields<'p>(&'p [u8]);
+
+impl<'p> Fields<'p> {
+    fn take(&mut self, len: usize) -> Option<&'p [u8]> {
+        let (bytes, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn number(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn bytes(&mut self) -> Option<&'p [u8]> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn list(&mut self) -> Option<Vec<String>> {
+        (0..self.len()?).map(|_| self.string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, FrDecompress, SyncIndex},
+        std::io::Cursor,
+    };
+
+    fn metadata() -> Metadata {
+        Metadata {
+            created: 1_700_000_000,
+            entries: 0,
+            dirs: 1,
+            files: 2,
+            files_bytes: 30,
+            elapsed: 4,
+            tool: "updatedb 0.1.8".to_owned(),
+            roots: vec!["C:\\".to_owned(), "D:\\".to_owned()],
+            options: vec![],
+            report: Some(Report {
+                entries: 0,
+                raw_bytes: 40,
+                compressed_bytes: 25,
+                prefix_lengths: vec![1, 0, 0, 2],
+                prefix_bytes: 9,
+                escapes_2b: 0,
+                escapes_4b: 0,
+                longest: b"C:\\\xff".to_vec(),
+            }),
+        }
+    }
+
+    #[test]
+    fn metadata_ok() {
+        let lines = Cursor::new("C:\\\nC:\\a\nD:\\b");
+        let mut compressed_lines = FrCompress::new(lines).with_metadata(metadata());
+        let db = compressed_lines.by_ref().collect::<Result<Vec<_>, _>>().unwrap().concat();
+
+        let expected = Metadata {
+            entries: 3,
+            report: Some(compressed_lines.report().clone()),
+            ..metadata()
+        };
+        assert_eq!(Metadata::read(&mut Cursor::new(&db)).unwrap(), Some(expected));
+        assert_eq!(SyncIndex::read(&mut Cursor::new(&db)).unwrap(), None);
+        assert_eq!(FrDecompress::new(Cursor::new(&db)).count(), 3);
+
+        let db = FrCompress::new(Cursor::new("C:\\"))
+            .sync_every(8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .concat();
+        assert_eq!(Metadata::read(&mut Cursor::new(&db)).unwrap(), None);
+    }
+
+    #[test]
+    fn newer_version_ok() {
+        let mut payload = metadata().to_bytes();
+        payload[0] = VERSION + 1;
+        payload.extend_from_slice(b"new fields");
+        assert_eq!(Metadata::from_bytes(&payload), Some(metadata()));
+
+        payload.truncate(20);
+        assert_eq!(Metadata::from_bytes(&payload), None);
+
+        // Version 1, without report
+        let metadata = Metadata { report: None, ..metadata() };
+        assert_eq!(metadata.to_bytes()[0], 1);
+        assert_eq!(Metadata::from_bytes(&metadata.to_bytes()), Some(metadata));
+    }
+
+    #[test]
+    fn too_many_buckets_err() {
+        let mut metadata = metadata();
+        metadata.report.as_mut().unwrap().prefix_lengths = vec![1; MAX_BUCKETS + 1];
+        assert_eq!(Metadata::from_bytes(&metadata.to_bytes()), None);
+    }
+}
@@ -0,0 +1,185 @@
+// mlocate.db, as written by mlocate's updatedb (see mlocate.db(5)) :
+//   header : MAGIC, configuration block size (4 bytes), version (1 byte, 0), visibility flag (1 byte),
+//            padding (2 bytes), NUL-terminated root path, configuration block
+//   then each directory : mtime (8 bytes), nanoseconds (4 bytes), padding (4 bytes), NUL-terminated path,
+//            then its entries : type (1 byte, 0 non-directory, 1 directory, 2 end of directory) and NUL-terminated name
+// All integers are big-endian.
+
+use crate::{Entry, FrError, Kind};
+
+/// Read the entries of a mlocate database held in memory, the root first, then each entry of each directory
+pub struct MlocateReader<'a> {
+    db: &'a [u8],
+    pos: usize,
+    entry: u64,
+    root: Option<&'a [u8]>,
+    dir: Option<&'a [u8]>,
+    done: bool,
+}
+
+impl<'a> MlocateReader<'a> {
+    pub const MAGIC: &'static [u8] = b"\0mlocate";
+
+    pub fn new(db: &'a [u8]) -> Result<MlocateReader<'a>, FrError> {
+        // The fixed part of the header is 16 bytes long
+        if !db.starts_with(MlocateReader::MAGIC) || db.len() < 16 || db[12] != 0 {
+            return Err(FrError::BadHeader);
+        }
+        let conf_size = u32::from_be_bytes(db[8..12].try_into().unwrap()) as usize;
+
+        let mut reader = MlocateReader {
+            db,
+            pos: 16,
+            entry: 0,
+            root: None,
+            dir: None,
+            done: false,
+        };
+        let root = reader.name().map_err(|_| FrError::BadHeader)?;
+        reader.root = Some(root);
+        reader.pos = reader
+            .pos
+            .checked_add(conf_size)
+            .filter(|pos| *pos <= db.len())
+            .ok_or(FrError::BadHeader)?;
+        Ok(reader)
+    }
+
+    fn truncated(&self) -> FrError {
+        FrError::Truncated {
+            offset: self.db.len() as u64,
+            entry: self.entry,
+        }
+    }
+
+    /// A NUL-terminated string
+    fn name(&mut self) -> Result<&'a [u8], FrError> {
+        let db = self.db;
+        let rest = db.get(self.pos..).ok_or_else(|| self.truncated())?;
+        let len = rest.iter().position(|b| *b == 0).ok_or_else(|| self.truncated())?;
+        let name = &db[self.pos..self.pos + len];
+        self.pos += len + 1;
+        Ok(name)
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Entry>, FrError> {
+        if let Some(root) = self.root.take() {
+            return Ok(Some(Entry {
+                path: root.to_vec(),
+                kind: Some(Kind::Dir),
+            }));
+        }
+
+        loop {
+            let Some(dir) = self.dir else {
+                if self.pos == self.db.len() {
+                    return Ok(None);
+                }
+                // The directory header
+                self.pos += 16;
+                if self.pos > self.db.len() {
+                    return Err(self.truncated());
+                }
+                self.dir = Some(self.name()?);
+                continue;
+            };
+
+            let entry_type = *self.db.get(self.pos).ok_or_else(|| self.truncated())?;
+            self.pos += 1;
+            let kind = match entry_type {
+                0 => Kind::File,
+                1 => Kind::Dir,
+                2 => {
+                    self.dir = None;
+                    continue;
+                }
+                _ => {
+                    return Err(FrError::InvalidOffset {
+                        offset: self.pos as u64 - 1,
+                        entry: self.entry,
+                    })
+                }
+            };
+
+            let name = self.name()?;
+            let mut path = Vec::with_capacity(dir.len() + 1 + name.len());
+            path.extend_from_slice(dir);
+            if !dir.ends_with(b"/") {
+                path.push(b'/');
+            }
+            path.extend_from_slice(name);
+            return Ok(Some(Entry { path, kind: Some(kind) }));
+        }
+    }
+}
+
+impl Iterator for MlocateReader<'_> {
+    type Item = Result<Entry, FrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_entry() {
+            Ok(Some(entry)) => {
+                self.entry += 1;
+                Some(Ok(entry))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true; // can't resynchronize after a bad entry
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(db: &mut Vec<u8>, path: &str, entries: &[(u8, &str)]) {
+        db.extend_from_slice(&[0; 16]);
+        db.extend_from_slice(path.as_bytes());
+        db.push(0);
+        for (entry_type, name) in entries {
+            db.push(*entry_type);
+            db.extend_from_slice(name.as_bytes());
+            db.push(0);
+        }
+        db.push(2);
+    }
+
+    #[test]
+    fn mlocate_ok() {
+        let mut db = b"\0mlocate\0\0\0\x04\0\x01\0\0/\0conf".to_vec();
+        dir(&mut db, "/", &[(1, "home"), (0, "vmlinuz")]);
+        dir(&mut db, "/home", &[(0, "a b"), (1, "u")]);
+        dir(&mut db, "/home/u", &[]);
+
+        let entries = MlocateReader::new(&db).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let expected = [
+            ("/", Kind::Dir),
+            ("/home", Kind::Dir),
+            ("/vmlinuz", Kind::File),
+            ("/home/a b", Kind::File),
+            ("/home/u", Kind::Dir),
+        ];
+        assert_eq!(
+            entries,
+            expected.map(|(path, kind)| Entry {
+                path: path.as_bytes().to_vec(),
+                kind: Some(kind)
+            })
+        );
+
+        assert!(matches!(MlocateReader::new(b"\0LOCATE02\0"), Err(FrError::BadHeader)));
+        assert!(matches!(MlocateReader::new(&db[..14]), Err(FrError::BadHeader)));
+        let mut reader = MlocateReader::new(&db[..db.len() - 4]).unwrap();
+        assert!(reader.any(|entry| matches!(entry, Err(FrError::Truncated { .. }))));
+    }
+}
@@ -0,0 +1,195 @@
+// plocate.db, as written by plocate-build and plocate's updatedb :
+//   header : MAGIC, version (4 bytes), hash table size (4), extra slots (4), number of blocks (4),
+//            offset of the hash table (8), offset of the filename index (8),
+//            since version 1 : max version (4), zstd dictionary length (4) and offset (8), then fields not needed here
+//   filename index : the offset of each block (8 bytes each), then the end of the last block
+//   each block : a zstd frame, compressed with the dictionary if any, of NUL-terminated paths
+// All integers are little-endian. The posting lists of the hash table aren't needed to list the entries.
+
+use {
+    crate::{block::read_block, Entry, FrError},
+    zstd::{dict::DecoderDictionary, stream::read::Decoder},
+};
+
+/// Read the entries of a plocate database held in memory, block by block
+pub struct PlocateReader<'a> {
+    db: &'a [u8],
+    dictionary: DecoderDictionary<'static>,
+    index: &'a [u8],
+    blocks: usize,
+    next_block: usize,
+    block: Vec<u8>,
+    pos: usize,
+    entry: u64,
+    done: bool,
+}
+
+fn u32_at(db: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(db.get(pos..pos + 4)?.try_into().unwrap()))
+}
+
+fn u64_at(db: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(db.get(pos..pos + 8)?.try_into().unwrap()))
+}
+
+impl<'a> PlocateReader<'a> {
+    pub const MAGIC: &'static [u8] = b"\0plocate";
+
+    pub fn new(db: &'a [u8]) -> Result<PlocateReader<'a>, FrError> {
+        if !db.starts_with(PlocateReader::MAGIC) {
+            return Err(FrError::BadHeader);
+        }
+        let version = u32_at(db, 8).ok_or(FrError::BadHeader)?;
+        let blocks = u32_at(db, 20).ok_or(FrError::BadHeader)? as usize;
+        let index_pos = u64_at(db, 32).ok_or(FrError::BadHeader)? as usize;
+        let index = index_pos
+            .checked_add((blocks + 1) * 8)
+            .and_then(|end| db.get(index_pos..end))
+            .ok_or(FrError::BadHeader)?;
+
+        let dictionary = if version >= 1 {
+            let len = u32_at(db, 44).ok_or(FrError::BadHeader)? as usize;
+            let pos = u64_at(db, 48).ok_or(FrError::BadHeader)? as usize;
+            pos.checked_add(len).and_then(|end| db.get(pos..end)).ok_or(FrError::BadHeader)?
+        } else {
+            &[]
+        };
+
+        Ok(PlocateReader {
+            db,
+            dictionary: DecoderDictionary::copy(dictionary),
+            index,
+            blocks,
+            next_block: 0,
+            block: Vec::new(),
+            pos: 0,
+            entry: 0,
+            done: false,
+        })
+    }
+
+    fn read_block(&mut self) -> Result<(), FrError> {
+        let block = self.next_block;
+        let start = u64_at(self.index, block * 8).unwrap() as usize;
+        let end = u64_at(self.index, block * 8 + 8).unwrap() as usize;
+        let compressed = self.db.get(start..end).ok_or(FrError::InvalidOffset {
+            offset: start as u64,
+            entry: self.entry,
+        })?;
+
+        self.block.clear();
+        read_block(Decoder::with_prepared_dictionary(compressed, &self.dictionary)?, &mut self.block)?;
+        self.next_block += 1;
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn read_entry(&mut self) -> Result<Option<Entry>, FrError> {
+        while self.pos == self.block.len() {
+            if self.next_block == self.blocks {
+                return Ok(None);
+            }
+            self.read_block()?;
+        }
+
+        let len = self.block[self.pos..].iter().position(|b| *b == 0).ok_or(FrError::Truncated {
+            offset: self.db.len() as u64,
+            entry: self.entry,
+        })?;
+        let path = self.block[self.pos..self.pos + len].to_vec();
+        self.pos += len + 1;
+        Ok(Some(Entry { path, kind: None }))
+    }
+}
+
+impl Iterator for PlocateReader<'_> {
+    type Item = Result<Entry, FrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_entry() {
+            Ok(Some(entry)) => {
+                self.entry += 1;
+                Some(Ok(entry))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true; // can't resynchronize after a bad entry
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, zstd::bulk::Compressor};
+
+    fn plocate_db(blocks: &[&[&str]], dictionary: &[u8]) -> Vec<u8> {
+        let mut compressor = Compressor::with_dictionary(3, dictionary).unwrap();
+        let blocks = blocks
+            .iter()
+            .map(|paths| {
+                compressor
+                    .compress(
+                        paths
+                            .iter()
+                            .flat_map(|path| [path.as_bytes(), b"\0"])
+                            .collect::<Vec<_>>()
+                            .concat()
+                            .as_slice(),
+                    )
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let header_len = 112;
+        let dictionary_pos = header_len;
+        let index_pos = dictionary_pos + dictionary.len();
+        let mut db = b"\0plocate".to_vec();
+        db.extend_from_slice(&1u32.to_le_bytes());
+        db.extend_from_slice(&[0; 8]);
+        db.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+        db.extend_from_slice(&0u64.to_le_bytes());
+        db.extend_from_slice(&(index_pos as u64).to_le_bytes());
+        db.extend_from_slice(&1u32.to_le_bytes());
+        db.extend_from_slice(&(dictionary.len() as u32).to_le_bytes());
+        db.extend_from_slice(&(dictionary_pos as u64).to_le_bytes());
+        db.resize(header_len, 0);
+        db.extend_from_slice(dictionary);
+
+        let mut pos = index_pos + (blocks.len() + 1) * 8;
+        for block in &blocks {
+            db.extend_from_slice(&(pos as u64).to_le_bytes());
+            pos += block.len();
+        }
+        db.extend_from_slice(&(pos as u64).to_le_bytes());
+        db.extend(blocks.concat());
+        db
+    }
+
+    #[test]
+    fn plocate_ok() {
+        let blocks: [&[&str]; 3] = [&["/", "/home", "/home/u"], &[], &["/usr/lib/libz.so"]];
+        for dictionary in [&b""[..], b"/home/usr/lib/"] {
+            let db = plocate_db(&blocks, dictionary);
+            let entries = PlocateReader::new(&db).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+            let paths = entries
+                .into_iter()
+                .map(|entry| String::from_utf8(entry.path).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(paths, blocks.concat());
+        }
+
+        assert!(matches!(PlocateReader::new(b"\0plocate\x01"), Err(FrError::BadHeader)));
+        let db = plocate_db(&blocks, b"");
+        let mut reader = PlocateReader::new(&db[..db.len() - 1]).unwrap();
+        assert!(reader.any(|entry| matches!(entry, Err(FrError::InvalidOffset { .. }))));
+    }
+}
@@ -0,0 +1,107 @@
+/// How well the entries compressed, filled in by the compressor and stored with the metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub entries: u64,
+    /// Bytes of the paths, a newline each
+    pub raw_bytes: u64,
+    /// Bytes of the database up to its footer
+    pub compressed_bytes: u64,
+    /// Number of entries by length of the prefix shared with the previous entry, in powers of 2 :
+    /// bucket 0 for no prefix, bucket `k` for 2^(k-1) to 2^k - 1 bytes
+    pub prefix_lengths: Vec<u64>,
+    /// Sum of the lengths of the shared prefixes
+    pub prefix_bytes: u64,
+    /// Counts that didn't fit in 1 byte, escaped to 2 bytes
+    pub escapes_2b: u64,
+    /// Counts escaped to 4 bytes
+    pub escapes_4b: u64,
+    /// The longest path
+    pub longest: Vec<u8>,
+}
+
+impl Report {
+    /// Account for an entry sharing `prefix_len` bytes with the previous one, whose counts took `counts` bytes each
+    pub(crate) fn entry(&mut self, line: &[u8], prefix_len: usize, counts: &[usize]) {
+        self.entries += 1;
+        self.raw_bytes += line.len() as u64 + 1;
+        let bucket = (usize::BITS - prefix_len.leading_zeros()) as usize;
+        if self.prefix_lengths.len() <= bucket {
+            self.prefix_lengths.resize(bucket + 1, 0);
+        }
+        self.prefix_lengths[bucket] += 1;
+        self.prefix_bytes += prefix_len as u64;
+        for count_len in counts {
+            match count_len {
+                3 => self.escapes_2b += 1,
+                7 => self.escapes_4b += 1,
+                _ => (),
+            }
+        }
+        if line.len() > self.longest.len() {
+            self.longest = line.to_vec();
+        }
+    }
+
+    /// Compressed bytes per raw byte, in percent
+    pub fn ratio(&self) -> f64 {
+        if self.raw_bytes == 0 {
+            return 0.0;
+        }
+        self.compressed_bytes as f64 * 100.0 / self.raw_bytes as f64
+    }
+
+    /// Average length of the shared prefixes
+    pub fn mean_prefix_len(&self) -> f64 {
+        if self.entries == 0 {
+            return 0.0;
+        }
+        self.prefix_bytes as f64 / self.entries as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{compress_file, FrCompress, Metadata},
+        std::{env, fs, io::Cursor, process},
+    };
+
+    #[test]
+    fn report_ok() {
+        let long = format!("/{}", "a".repeat(200));
+        let lines = ["/usr", "/usr/lib", "/usr/lib/x", &long, "/v"];
+        let mut compressed_lines = FrCompress::new(Cursor::new(lines.join("\n"))).with_metadata(Metadata::default());
+        let db = compressed_lines.by_ref().collect::<Result<Vec<_>, _>>().unwrap().concat();
+
+        let report = compressed_lines.report();
+        assert_eq!(report.entries, 5);
+        assert_eq!(report.raw_bytes, lines.iter().map(|line| line.len() as u64 + 1).sum());
+        assert!(report.compressed_bytes < db.len() as u64);
+        // Prefixes of 0, 4, 8, 1 and 1 bytes
+        assert_eq!(report.prefix_lengths, [1, 2, 0, 1, 1]);
+        assert_eq!(report.mean_prefix_len(), 14.0 / 5.0);
+        // The suffix length of the long entry
+        assert_eq!((report.escapes_2b, report.escapes_4b), (1, 0));
+        assert_eq!(report.longest, long.as_bytes());
+
+        // Stored with the metadata
+        let metadata = Metadata::read(&mut Cursor::new(&db)).unwrap().unwrap();
+        assert_eq!(metadata.report.as_ref(), Some(report));
+
+        // A long shared prefix takes one bucket
+        let longer = format!("/{}", "a".repeat(100_000));
+        let mut compressed_lines = FrCompress::new(Cursor::new(format!("{longer}\n{longer}/b"))).with_metadata(Metadata::default());
+        let long_db = compressed_lines.by_ref().collect::<Result<Vec<_>, _>>().unwrap().concat();
+        assert_eq!(compressed_lines.report().prefix_lengths.len(), 18);
+        assert!(long_db.len() < 250_000);
+
+        let in_file = env::temp_dir().join(format!("frreport-{}.txt", process::id()));
+        let out_file = in_file.with_extension("db");
+        fs::write(&in_file, lines.join("\n")).unwrap();
+        let report = compress_file(&in_file, &out_file, None).unwrap();
+        assert_eq!(report.entries, 5);
+        assert!(report.compressed_bytes > 0 && report.compressed_bytes < fs::metadata(&out_file).unwrap().len());
+        fs::remove_file(in_file).unwrap();
+        fs::remove_file(out_file).unwrap();
+    }
+}
@@ -0,0 +1,382 @@
+use {
+    crate::{
+        block::{decompress_block, COMPRESSED},
+        index::{BLOCK, CONTROL, END},
+        stat::read_stat,
+        Format, FrError, Kind, Records, Stat, SyncIndex, ESCAPE_2B, ESCAPE_4B, LOCATE02, LOCATEW,
+    },
+    std::ops::Range,
+};
+
+/// Decompress a database held in memory, such as a memory-mapped locate.db.
+/// Each entry is borrowed until the next one is decoded, so nothing is allocated per entry.
+/// The blocks of a compressed database are decompressed one at a time.
+pub struct FrDecompressSlice<'a> {
+    db: &'a [u8],
+    done: bool,
+    restart: bool,
+    format: Format,
+    records: Records,
+    pos: usize,
+    record_pos: usize,
+    entry: u64,
+    prec_prefix_len: usize,
+    line: Vec<u8>,
+    stat: Option<Stat>,
+    kind: Option<Kind>,
+    block: Vec<u8>,
+    block_pos: usize,
+    in_block: bool,
+}
+
+impl<'a> FrDecompressSlice<'a> {
+    pub fn new(db: &'a [u8]) -> Result<FrDecompressSlice<'a>, FrError> {
+        let mut decompressed_lines = FrDecompressSlice {
+            db,
+            done: db.is_empty(), // empty database
+            restart: false,
+            format: Format::default(),
+            records: Records::default(),
+            pos: 0,
+            record_pos: 0,
+            entry: 0,
+            prec_prefix_len: 0,
+            line: Vec::with_capacity(1_000),
+            stat: None,
+            kind: None,
+            block: Vec::new(),
+            block_pos: 0,
+            in_block: false,
+        };
+
+        if !db.is_empty() {
+            // Skip the offset, LOCATEW stores the label length, LOCATE02 starts the label right away
+            if db.get(1) == Some(&(LOCATEW.len() as u8)) && db.get(2..2 + LOCATEW.len()) == Some(LOCATEW) {
+                decompressed_lines.format = Format::LocateW;
+                decompressed_lines.pos = 2 + LOCATEW.len();
+            } else if db.get(1) == Some(&(LOCATEW.len() as u8 + 1)) && db.get(2..2 + LOCATEW.len()) == Some(LOCATEW) {
+                // The label is followed by the layout of the records
+                let records = db.get(2 + LOCATEW.len()).and_then(|records| Records::from_byte(*records & !COMPRESSED));
+                decompressed_lines.format = Format::LocateW;
+                decompressed_lines.records = records.ok_or(FrError::BadHeader)?;
+                decompressed_lines.pos = 3 + LOCATEW.len();
+            } else if db.get(1..1 + LOCATE02.len()) == Some(LOCATE02) && db.get(1 + LOCATE02.len()) == Some(&0) {
+                decompressed_lines.format = Format::Locate02;
+                decompressed_lines.pos = 2 + LOCATE02.len();
+            } else {
+                return Err(FrError::BadHeader);
+            }
+        }
+
+        Ok(decompressed_lines)
+    }
+
+    /// Decompress from the sync point starting `block`, up to the end of the database
+    pub fn at_sync_point(db: &'a [u8], index: &SyncIndex, block: usize) -> Result<FrDecompressSlice<'a>, FrError> {
+        Ok(FrDecompressSlice {
+            db,
+            done: false,
+            restart: true,
+            format: index.format,
+            records: index.records,
+            pos: index.offset(block)? as usize,
+            record_pos: 0,
+            entry: block as u64 * index.interval as u64,
+            prec_prefix_len: 0,
+            line: Vec::with_capacity(1_000),
+            stat: None,
+            kind: None,
+            block: Vec::new(),
+            block_pos: 0,
+            in_block: false,
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn records(&self) -> Records {
+        self.records
+    }
+
+    /// The metadata of the last entry returned, if the database stores it
+    pub fn stat(&self) -> Option<Stat> {
+        self.stat
+    }
+
+    /// The kind of the last entry returned, if the database stores it
+    pub fn kind(&self) -> Option<Kind> {
+        self.kind
+    }
+
+    /// The next entry, borrowed until the following call
+    pub fn next_entry(&mut self) -> Option<Result<&[u8], FrError>> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_entry() {
+            Ok(true) => Some(Ok(&self.line)),
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true; // can't resynchronize after a bad entry
+                Some(Err(err))
+            }
+        }
+    }
+
+    /// Call `f` on each remaining entry, its kind and its metadata, until it returns false
+    pub fn for_each_entry(&mut self, mut f: impl FnMut(&[u8], Option<Kind>, Option<Stat>) -> bool) -> Result<(), FrError> {
+        while !self.done {
+            match self.read_entry() {
+                Ok(true) if f(&self.line, self.kind, self.stat) => (),
+                Ok(true) => break,
+                Ok(false) => self.done = true,
+                Err(err) => {
+                    self.done = true;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn truncated(&self) -> FrError {
+        FrError::Truncated {
+            offset: self.db.len() as u64,
+            entry: self.entry,
+        }
+    }
+
+    fn invalid_offset(&self) -> FrError {
+        FrError::InvalidOffset {
+            offset: self.record_pos as u64,
+            entry: self.entry,
+        }
+    }
+
+    /// The bytes being decoded : the database, or the block being read
+    fn input(&self) -> &[u8] {
+        if self.in_block {
+            &self.block
+        } else {
+            self.db
+        }
+    }
+
+    /// The position of the next `len` bytes of the input
+    fn bytes(&mut self, len: usize) -> Result<Range<usize>, FrError> {
+        let (input_len, pos) = if self.in_block {
+            (self.block.len(), self.block_pos)
+        } else {
+            (self.db.len(), self.pos)
+        };
+        let end = pos.checked_add(len).filter(|end| *end <= input_len).ok_or_else(|| self.truncated())?;
+        if self.in_block {
+            self.block_pos = end;
+        } else {
+            self.pos = end;
+        }
+        Ok(pos..end)
+    }
+
+    fn byte(&mut self) -> Result<u8, FrError> {
+        let range = self.bytes(1)?;
+        Ok(self.input()[range.start])
+    }
+
+    fn count(&mut self) -> Result<i32, FrError> {
+        let count_1b = self.byte()?;
+        if count_1b != ESCAPE_2B {
+            return Ok(count_1b as i8 as i32);
+        }
+
+        let range = self.bytes(2)?;
+        let count_2b = &self.input()[range];
+        if count_2b != ESCAPE_4B {
+            Ok(i16::from_be_bytes([count_2b[0], count_2b[1]]) as i32)
+        } else {
+            let range = self.bytes(4)?;
+            Ok(i32::from_be_bytes(self.input()[range].try_into().unwrap()))
+        }
+    }
+
+    /// Decompress a BLOCK control record, its entries are read next
+    fn read_block(&mut self, payload: Range<usize>) -> Result<(), FrError> {
+        decompress_block(&self.db[payload], &mut self.block).map_err(|_| self.invalid_offset())?;
+        self.block_pos = 0;
+        self.in_block = true;
+        self.restart = true; // each block starts with a sync point
+        Ok(())
+    }
+
+    fn read_entry(&mut self) -> Result<bool, FrError> {
+        let offset = loop {
+            if self.in_block && self.block_pos == self.block.len() {
+                self.in_block = false;
+            }
+            if !self.in_block {
+                self.record_pos = self.pos;
+                if self.pos == self.db.len() {
+                    return Ok(false); // end of valid updateDB file happens here
+                }
+            }
+            let offset = self.count()?;
+            if offset != CONTROL {
+                break offset;
+            }
+            if self.in_block {
+                return Err(self.invalid_offset());
+            }
+
+            let tag = self.byte()?;
+            let len = self.bytes(4)?;
+            let len = u32::from_be_bytes(self.db[len].try_into().unwrap());
+            let payload = self.bytes(len as usize)?;
+            match tag {
+                END => return Ok(false), // the footer follows
+                BLOCK => self.read_block(payload)?,
+                _ => (), // not needed to decompress the entries
+            }
+        };
+
+        // A sync point doesn't depend on the previous entry
+        let prefix_len = if self.restart {
+            self.restart = false;
+            0
+        } else {
+            self.prec_prefix_len as i64 + offset as i64
+        };
+        if prefix_len < 0 || prefix_len as usize > self.line.len() {
+            return Err(self.invalid_offset());
+        }
+
+        let suffix = match self.format {
+            Format::LocateW => {
+                let suffix_len = self.count()?;
+                if suffix_len < 0 {
+                    return Err(self.invalid_offset());
+                }
+                self.bytes(suffix_len as usize)?
+            }
+            Format::Locate02 => {
+                let start = if self.in_block { self.block_pos } else { self.pos };
+                let len = self.input()[start..].iter().position(|b| *b == 0).ok_or_else(|| self.truncated())?;
+                let suffix = self.bytes(len)?;
+                self.bytes(1)?; // NUL
+                suffix
+            }
+        };
+
+        match self.records {
+            Records::Stats => {
+                let (offset, entry) = (self.record_pos as u64, self.entry);
+                let prec_mtime = self.stat.map_or(0, |stat| stat.mtime);
+                let invalid = move || FrError::InvalidOffset { offset, entry };
+                let stat = read_stat(|| self.byte(), invalid, prec_mtime)?;
+                self.stat = Some(stat);
+                self.kind = Some(stat.kind);
+            }
+            Records::Kinds => {
+                let kind_byte = self.byte()?;
+                self.kind = Some(Kind::from_byte(kind_byte).ok_or_else(|| self.invalid_offset())?);
+            }
+            Records::Paths => (),
+        }
+
+        let input = if self.in_block { &self.block[..] } else { self.db };
+        self.line.truncate(prefix_len as usize);
+        self.line.extend_from_slice(&input[suffix]);
+        self.prec_prefix_len = prefix_len as usize;
+        self.entry += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, FrDecompressBytes},
+        std::io::Cursor,
+    };
+
+    #[test]
+    fn same_as_stream_ok() {
+        let dirlist = (0..500)
+            .map(|i| format!("/usr/lib/{:02}/Bébé{}", i / 7, "é".repeat(i % 5)))
+            .collect::<Vec<String>>();
+
+        for format in [Format::LocateW, Format::Locate02] {
+            for interval in [0, 16] {
+                let lines = Cursor::new(dirlist.join("\n"));
+                let compressed_lines = FrCompress::with_format(lines, format).sync_every(interval);
+                let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+
+                let expected = FrDecompressBytes::new(Cursor::new(&db)).collect::<Result<Vec<_>, _>>().unwrap();
+                let mut decompressed_lines = FrDecompressSlice::new(&db).unwrap();
+                assert_eq!(decompressed_lines.format(), format);
+                let mut lines = Vec::new();
+                decompressed_lines
+                    .for_each_entry(|line, _, _| {
+                        lines.push(line.to_vec());
+                        true
+                    })
+                    .unwrap();
+                assert_eq!(expected, lines);
+            }
+        }
+    }
+
+    #[test]
+    fn at_sync_point_ok() {
+        let dirlist = (0..100).map(|i| format!("/var/{:02}/{}", i / 3, i)).collect::<Vec<String>>();
+        let compressed_lines = FrCompress::new(Cursor::new(dirlist.join("\n"))).sync_every(10);
+        let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+        let index = SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap();
+
+        let mut decompressed_lines = FrDecompressSlice::at_sync_point(&db, &index, 4).unwrap();
+        for line in &dirlist[40..] {
+            assert_eq!(decompressed_lines.next_entry().unwrap().unwrap(), line.as_bytes());
+        }
+        assert!(decompressed_lines.next_entry().is_none());
+
+        assert!(matches!(
+            FrDecompressSlice::at_sync_point(&db, &index, 10),
+            Err(FrError::NoBlock { block: 10, blocks: 10 })
+        ));
+        assert!(matches!(
+            FrDecompressBytes::at_sync_point(Cursor::new(&db), &index, 10),
+            Err(FrError::NoBlock { .. })
+        ));
+        assert!(matches!(index.check_blocks(&db, 8..11), Err(FrError::NoBlock { block: 10, .. })));
+    }
+
+    #[test]
+    fn corrupt_err() {
+        assert!(matches!(FrDecompressSlice::new(b"\0\x07LOCATEX"), Err(FrError::BadHeader)));
+        assert!(FrDecompressSlice::new(b"").unwrap().next_entry().is_none());
+
+        let mut decompressed_lines = FrDecompressSlice::new(b"\0\x07LOCATEW\0\x04/usr\x04\x05/b").unwrap();
+        assert_eq!(decompressed_lines.next_entry().unwrap().unwrap(), b"/usr");
+        assert!(matches!(
+            decompressed_lines.next_entry(),
+            Some(Err(FrError::Truncated { offset: 19, entry: 1 }))
+        ));
+        assert!(decompressed_lines.next_entry().is_none());
+
+        let mut decompressed_lines = FrDecompressSlice::new(b"\0LOCATE02\0\0/usr\0\x05b\0").unwrap();
+        assert!(decompressed_lines.next_entry().unwrap().is_ok());
+        assert!(matches!(
+            decompressed_lines.next_entry(),
+            Some(Err(FrError::InvalidOffset { offset: 16, entry: 1 }))
+        ));
+
+        let mut decompressed_lines = FrDecompressSlice::new(b"\0LOCATE02\0\0/usr").unwrap();
+        assert!(matches!(decompressed_lines.next_entry(), Some(Err(FrError::Truncated { .. }))));
+    }
+}
@@ -0,0 +1,168 @@
+use {
+    crate::{merge::Merged, Entry, FrDecompressBytes, FrError, FrWriter, Kind, Records, Stat},
+    std::env,
+    std::fs::{remove_file, File},
+    std::io::{BufReader, BufWriter},
+    std::mem,
+    std::path::PathBuf,
+    std::process,
+    std::sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Memory used by the sorted-build mode of `FrCompress` and `FrWriter`, before spilling the entries to disk
+pub const DEFAULT_SORT_MEMORY: usize = 64 << 20;
+
+/// Each entry costs its path, plus its place in the buffer
+const ENTRY_OVERHEAD: usize = mem::size_of::<(Entry, Option<Stat>)>();
+
+static RUN_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) type Sorted = Box<dyn Iterator<Item = Result<(Entry, Option<Stat>), FrError>>>;
+
+/// Temporary files removed when dropped
+struct Runs(Vec<PathBuf>);
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for run in &self.0 {
+            let _ = remove_file(run);
+        }
+    }
+}
+
+/// The merge of the runs, which are removed once it's dropped
+struct MergedRuns {
+    merged: Merged<'static>,
+    _runs: Runs,
+}
+
+impl Iterator for MergedRuns {
+    type Item = Result<(Entry, Option<Stat>), FrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.merged.next()
+    }
+}
+
+/// External merge sort of the entries in byte order : the entries are sorted in memory up to `max_memory`,
+/// then spilled to a temporary database (a run), and the runs are merged at the end. Duplicate paths are kept once.
+pub(crate) struct Sorter {
+    max_memory: usize,
+    records: Records,
+    buffer: Vec<(Entry, Option<Stat>)>,
+    buffered: usize,
+    runs: Runs,
+}
+
+impl Sorter {
+    pub(crate) fn new(records: Records, max_memory: usize) -> Sorter {
+        Sorter {
+            max_memory,
+            records,
+            buffer: Vec::new(),
+            buffered: 0,
+            runs: Runs(Vec::new()),
+        }
+    }
+
+    pub(crate) fn push(&mut self, path: &[u8], kind: Kind, stat: Option<Stat>) -> Result<(), FrError> {
+        let entry = Entry {
+            path: path.to_vec(),
+            kind: Some(kind),
+        };
+        self.buffer.push((entry, stat));
+        self.buffered += path.len() + ENTRY_OVERHEAD;
+        if self.buffered >= self.max_memory {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// The buffer sorted in byte order, the first duplicate kept
+    fn take_sorted(&mut self) -> Vec<(Entry, Option<Stat>)> {
+        let mut buffer = mem::take(&mut self.buffer);
+        buffer.sort_by(|a, b| a.0.path.cmp(&b.0.path));
+        buffer.dedup_by(|a, b| a.0.path == b.0.path);
+        self.buffered = 0;
+        buffer
+    }
+
+    /// Write the sorted buffer to a run. The runs keep the metadata, or the kind of each entry.
+    fn spill(&mut self) -> Result<(), FrError> {
+        let run_id = RUN_ID.fetch_add(1, Ordering::Relaxed);
+        let run = env::temp_dir().join(format!("frsort-{}-{run_id}.tmp", process::id()));
+        let file = BufWriter::new(File::create(&run)?);
+        self.runs.0.push(run);
+
+        let mut writer = if self.records == Records::Stats {
+            FrWriter::with_stats(file)
+        } else {
+            FrWriter::with_kinds(file)
+        };
+        for (entry, stat) in self.take_sorted() {
+            match stat {
+                Some(stat) => writer.push_stat(&entry.path, stat)?,
+                None => writer.push(&entry.path, entry.kind.unwrap_or_default())?,
+            }
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// All the entries pushed, in byte order
+    pub(crate) fn sorted(mut self) -> Result<Sorted, FrError> {
+        if self.runs.0.is_empty() {
+            return Ok(Box::new(self.take_sorted().into_iter().map(Ok)));
+        }
+
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        let runs = mem::replace(&mut self.runs, Runs(Vec::new()));
+        let mut inputs = Vec::with_capacity(runs.0.len());
+        for run in &runs.0 {
+            inputs.push(FrDecompressBytes::new(BufReader::new(File::open(run)?)));
+        }
+        Ok(Box::new(MergedRuns {
+            merged: Merged::new(inputs)?,
+            _runs: runs,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorter_ok() {
+        let paths = (0..1_000)
+            .map(|i| format!("/d{}/{}", i * 7 % 13, i * 31 % 1_000))
+            .collect::<Vec<String>>();
+        let mut expected = paths.clone();
+        expected.sort();
+
+        for max_memory in [usize::MAX, 2_000] {
+            let mut sorter = Sorter::new(Records::Stats, max_memory);
+            for (i, path) in paths.iter().chain(&paths[..10]).enumerate() {
+                let stat = Stat {
+                    size: i as u64,
+                    ..Default::default()
+                };
+                sorter.push(path.as_bytes(), Kind::File, Some(stat)).unwrap();
+            }
+            let spilled = sorter.runs.0.clone();
+            assert_eq!(spilled.is_empty(), max_memory == usize::MAX);
+
+            let sorted = sorter.sorted().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+            let sorted_paths = sorted
+                .iter()
+                .map(|(entry, _)| String::from_utf8(entry.path.clone()).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(sorted_paths, expected);
+            // The first duplicate is kept
+            assert!(sorted.iter().all(|(_, stat)| stat.unwrap().size < 1_000));
+            assert!(spilled.iter().all(|run| !run.exists()));
+        }
+    }
+}
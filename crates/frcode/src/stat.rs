@@ -0,0 +1,204 @@
+// In a Records::Stats database, each record ends with :
+//   kind (1 byte), with ABSOLUTE set when mtime doesn't depend on the previous record
+//   size (LEB128)
+//   mtime (zigzag LEB128), the difference with the previous record's mtime
+// The first record and the sync points have an absolute mtime.
+// In a Records::Kinds database, each record ends with the kind byte only.
+
+use crate::FrError;
+
+const ABSOLUTE: u8 = 0x80;
+
+/// Type of a file system entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Kind {
+    #[default]
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+/// A path and its kind, if the database stores it. Directories are no longer marked with a trailing separator.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Entry {
+    pub path: Vec<u8>,
+    pub kind: Option<Kind>,
+}
+
+impl Entry {
+    /// The last component of the path, after the last `/` (or `\\` on Windows)
+    pub fn name(&self) -> &[u8] {
+        base_name(&self.path)
+    }
+}
+
+/// The last component of a raw path
+pub fn base_name(path: &[u8]) -> &[u8] {
+    path.rsplit(|b| std::path::is_separator(*b as char)).next().unwrap()
+}
+
+/// Metadata of an entry, stored by `Records::Stats` databases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stat {
+    pub kind: Kind,
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub mtime: i64,
+}
+
+/// What each record stores besides the path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Records {
+    #[default]
+    Paths,
+    Stats,
+    /// The kind of each entry, without its size nor mtime
+    Kinds,
+}
+
+impl Records {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            Records::Paths => 0,
+            Records::Stats => 1,
+            Records::Kinds => 2,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Records> {
+        match byte {
+            0 => Some(Records::Paths),
+            1 => Some(Records::Stats),
+            2 => Some(Records::Kinds),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn push_varint(out_bytes: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out_bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out_bytes.push(n as u8);
+}
+
+pub(crate) fn read_varint(mut byte: impl FnMut() -> Result<u8, FrError>, invalid: impl Fn() -> FrError) -> Result<u64, FrError> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let b = byte()?;
+        n |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(invalid())
+}
+
+impl Kind {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            Kind::File => 0,
+            Kind::Dir => 1,
+            Kind::Symlink => 2,
+            Kind::Other => 3,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Kind> {
+        match byte {
+            0 => Some(Kind::File),
+            1 => Some(Kind::Dir),
+            2 => Some(Kind::Symlink),
+            3 => Some(Kind::Other),
+            _ => None,
+        }
+    }
+}
+
+/// `prec_mtime` is None when the mtime must be absolute
+pub(crate) fn push_stat(out_bytes: &mut Vec<u8>, stat: &Stat, prec_mtime: Option<i64>) {
+    let kind = stat.kind.to_byte();
+    let delta = stat.mtime.wrapping_sub(prec_mtime.unwrap_or(0));
+    out_bytes.push(if prec_mtime.is_some() { kind } else { kind | ABSOLUTE });
+    push_varint(out_bytes, stat.size);
+    push_varint(out_bytes, ((delta << 1) ^ (delta >> 63)) as u64);
+}
+
+pub(crate) fn read_stat(mut byte: impl FnMut() -> Result<u8, FrError>, invalid: impl Fn() -> FrError, prec_mtime: i64) -> Result<Stat, FrError> {
+    let kind_byte = byte()?;
+    let kind = Kind::from_byte(kind_byte & !ABSOLUTE).ok_or_else(&invalid)?;
+    let size = read_varint(&mut byte, &invalid)?;
+    let zigzag = read_varint(&mut byte, &invalid)?;
+    let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+    let mtime = if kind_byte & ABSOLUTE != 0 {
+        delta
+    } else {
+        prec_mtime.wrapping_add(delta)
+    };
+
+    Ok(Stat { kind, size, mtime })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_ok() {
+        let stats = [
+            Stat {
+                kind: Kind::Dir,
+                size: 0,
+                mtime: 1_700_000_000,
+            },
+            Stat {
+                kind: Kind::File,
+                size: 1 << 40,
+                mtime: 1_600_000_000,
+            },
+            Stat {
+                kind: Kind::Symlink,
+                size: 127,
+                mtime: -86_400,
+            },
+            Stat {
+                kind: Kind::Other,
+                size: u64::MAX,
+                mtime: i64::MAX,
+            },
+            Stat {
+                kind: Kind::File,
+                size: 128,
+                mtime: i64::MIN,
+            },
+        ];
+
+        let mut out_bytes = Vec::new();
+        let mut prec_mtime = None;
+        for stat in &stats {
+            push_stat(&mut out_bytes, stat, prec_mtime);
+            prec_mtime = Some(stat.mtime);
+        }
+
+        let mut bytes = out_bytes.into_iter();
+        let mut prec_mtime = 0;
+        for stat in &stats {
+            let decoded = read_stat(|| Ok(bytes.next().unwrap()), || FrError::BadHeader, prec_mtime).unwrap();
+            assert_eq!(*stat, decoded);
+            prec_mtime = decoded.mtime;
+        }
+        assert!(bytes.next().is_none());
+    }
+
+    #[test]
+    fn varint_err() {
+        let mut bytes = [0xff; 11].into_iter();
+        let invalid = || FrError::InvalidOffset { offset: 0, entry: 0 };
+        assert!(matches!(
+            read_varint(|| Ok(bytes.next().unwrap()), invalid),
+            Err(FrError::InvalidOffset { .. })
+        ));
+    }
+}
@@ -0,0 +1,240 @@
+// Trigram sidecar, written next to a database with sync points :
+//   TRIGRAM_MAGIC, version (1 byte)
+//   database length (8 bytes), sync interval (4 bytes), number of blocks (4 bytes), number of trigrams (4 bytes)
+//   table : each trigram (3 bytes) in byte order, then the offset of its postings (8 bytes)
+//   postings : the blocks containing the trigram, each in LEB128 as the difference with the previous block + 1
+// Trigrams are ASCII lowercase. All integers are big-endian.
+
+use {
+    crate::{
+        stat::{push_varint, read_varint},
+        FrError, SyncIndex,
+    },
+    std::collections::HashMap,
+};
+
+const TRIGRAM_MAGIC: &[u8; 8] = b"FRTRIGRM";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 9 + 8 + 4 + 4 + 4;
+const ROW_LEN: usize = 3 + 8;
+
+type Trigram = [u8; 3];
+
+fn trigrams(bytes: &[u8]) -> impl Iterator<Item = Trigram> + '_ {
+    bytes
+        .windows(3)
+        .map(|t| [t[0].to_ascii_lowercase(), t[1].to_ascii_lowercase(), t[2].to_ascii_lowercase()])
+}
+
+#[derive(Default)]
+struct Postings {
+    next: u32,
+    bytes: Vec<u8>,
+}
+
+/// Build the trigram sidecar from the entries of a database, in the same order
+pub struct TrigramBuilder {
+    interval: u32,
+    entry: u64,
+    postings: HashMap<Trigram, Postings>,
+}
+
+impl TrigramBuilder {
+    /// `interval` must be the sync interval of the database
+    pub fn new(interval: u32) -> TrigramBuilder {
+        TrigramBuilder {
+            interval,
+            entry: 0,
+            postings: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, path: &[u8]) {
+        let block = (self.entry / self.interval as u64) as u32;
+        for trigram in trigrams(path) {
+            let postings = self.postings.entry(trigram).or_default();
+            if postings.next <= block {
+                push_varint(&mut postings.bytes, (block - postings.next) as u64);
+                postings.next = block + 1;
+            }
+        }
+        self.entry += 1;
+    }
+
+    /// The sidecar of a database `db_len` bytes long
+    pub fn finish(self, db_len: u64) -> Vec<u8> {
+        let blocks = self.entry.div_ceil(self.interval as u64) as u32;
+        let mut table = self.postings.into_iter().collect::<Vec<_>>();
+        table.sort_unstable_by_key(|(trigram, _)| *trigram);
+
+        let postings_len = table.iter().map(|(_, postings)| postings.bytes.len()).sum::<usize>();
+        let mut out_bytes = Vec::with_capacity(HEADER_LEN + table.len() * ROW_LEN + postings_len);
+        out_bytes.extend_from_slice(TRIGRAM_MAGIC);
+        out_bytes.push(VERSION);
+        out_bytes.extend_from_slice(&db_len.to_be_bytes());
+        out_bytes.extend_from_slice(&self.interval.to_be_bytes());
+        out_bytes.extend_from_slice(&blocks.to_be_bytes());
+        out_bytes.extend_from_slice(&(table.len() as u32).to_be_bytes());
+
+        let mut offset = 0u64;
+        for (trigram, postings) in &table {
+            out_bytes.extend_from_slice(trigram);
+            out_bytes.extend_from_slice(&offset.to_be_bytes());
+            offset += postings.bytes.len() as u64;
+        }
+        for (_, postings) in table {
+            out_bytes.extend_from_slice(&postings.bytes);
+        }
+        out_bytes
+    }
+}
+
+/// Trigram sidecar held in memory, such as a memory-mapped locate.tri
+pub struct TrigramIndex<'a> {
+    db_len: u64,
+    interval: u32,
+    blocks: u32,
+    table: &'a [u8],
+    postings: &'a [u8],
+}
+
+impl<'a> TrigramIndex<'a> {
+    pub fn new(sidecar: &'a [u8]) -> Result<TrigramIndex<'a>, FrError> {
+        if sidecar.len() < HEADER_LEN || &sidecar[..8] != TRIGRAM_MAGIC || sidecar[8] != VERSION {
+            return Err(FrError::BadHeader);
+        }
+        let number = |pos: usize| u32::from_be_bytes(sidecar[pos..pos + 4].try_into().unwrap());
+        let table_len = number(25) as usize * ROW_LEN;
+        let (table, postings) = sidecar[HEADER_LEN..].split_at_checked(table_len).ok_or(FrError::Truncated {
+            offset: sidecar.len() as u64,
+            entry: 0,
+        })?;
+
+        Ok(TrigramIndex {
+            db_len: u64::from_be_bytes(sidecar[9..17].try_into().unwrap()),
+            interval: number(17),
+            blocks: number(21),
+            table,
+            postings,
+        })
+    }
+
+    /// Whether the sidecar was built for this database, rather than a previous one
+    pub fn is_for(&self, db_len: u64, index: &SyncIndex) -> bool {
+        self.db_len == db_len && self.interval == index.interval && self.blocks as usize == index.blocks()
+    }
+
+    fn row(&self, i: usize) -> (&'a [u8], usize) {
+        let row = &self.table[i * ROW_LEN..(i + 1) * ROW_LEN];
+        (&row[..3], u64::from_be_bytes(row[3..].try_into().unwrap()) as usize)
+    }
+
+    fn postings(&self, trigram: &Trigram) -> Result<Vec<u32>, FrError> {
+        let rows = self.table.len() / ROW_LEN;
+        let (mut low, mut high) = (0, rows);
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.row(mid).0 < trigram.as_slice() {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == rows || self.row(low).0 != trigram.as_slice() {
+            return Ok(Vec::new());
+        }
+
+        let start = self.row(low).1;
+        let end = if low + 1 < rows { self.row(low + 1).1 } else { self.postings.len() };
+        let invalid = || FrError::InvalidOffset {
+            offset: (HEADER_LEN + self.table.len() + start) as u64,
+            entry: 0,
+        };
+        let mut bytes = self.postings.get(start..end).ok_or_else(invalid)?.iter();
+
+        let mut blocks = Vec::new();
+        let mut block = 0u64;
+        while bytes.len() > 0 {
+            block += read_varint(|| bytes.next().copied().ok_or_else(invalid), invalid)?;
+            blocks.push(block as u32);
+            block += 1;
+        }
+        Ok(blocks)
+    }
+
+    /// The blocks that may contain `literal`, ignoring ASCII case. `None` if it has no usable trigram.
+    /// With `ignore_case`, the trigrams with a non-ASCII byte are left out : the other cases of a non-ASCII
+    /// character aren't indexed.
+    pub fn candidates(&self, literal: &[u8], ignore_case: bool) -> Result<Option<Vec<u32>>, FrError> {
+        let mut candidates: Option<Vec<u32>> = None;
+        for trigram in trigrams(literal).filter(|trigram| !ignore_case || trigram.is_ascii()) {
+            let blocks = self.postings(&trigram)?;
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.into_iter().filter(|block| blocks.binary_search(block).is_ok()).collect(),
+                None => blocks,
+            });
+            if candidates.as_ref().is_some_and(Vec::is_empty) {
+                break;
+            }
+        }
+        Ok(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::FrCompress, std::io::Cursor};
+
+    #[test]
+    fn candidates_ok() {
+        let dirlist = (0..1000)
+            .map(|i| format!("C:\\Projets\\{:03}\\Rapport{}.DOCX", i / 10, i % 37))
+            .collect::<Vec<String>>();
+        let compressed_lines = FrCompress::new(Cursor::new(dirlist.join("\n"))).sync_every(16);
+        let db = compressed_lines.collect::<Result<Vec<_>, _>>().unwrap().concat();
+        let index = SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap();
+
+        let mut builder = TrigramBuilder::new(16);
+        dirlist.iter().for_each(|line| builder.push(line.as_bytes()));
+        let sidecar = builder.finish(db.len() as u64);
+        let trigrams = TrigramIndex::new(&sidecar).unwrap();
+        assert!(trigrams.is_for(db.len() as u64, &index));
+        assert!(!trigrams.is_for(db.len() as u64 + 1, &index));
+
+        for literal in ["rapport36", "\\042\\", "PROJETS", ".docx"] {
+            let expected = (0..index.blocks() as u32)
+                .filter(|block| {
+                    let block = &dirlist[*block as usize * 16..((*block as usize + 1) * 16).min(dirlist.len())];
+                    block.iter().any(|line| line.to_lowercase().contains(&literal.to_lowercase()))
+                })
+                .collect::<Vec<u32>>();
+            assert_eq!(trigrams.candidates(literal.as_bytes(), true).unwrap(), Some(expected));
+        }
+        assert_eq!(trigrams.candidates(b"zip", true).unwrap(), Some(vec![]));
+        assert_eq!(trigrams.candidates(b"pr", true).unwrap(), None);
+    }
+
+    #[test]
+    fn non_ascii_ok() {
+        let dirlist = ["/photos/bébé/été.jpg", "/photos/chat.jpg", "/docs/rapport.pdf", "/docs/index.html"];
+        let mut builder = TrigramBuilder::new(1);
+        dirlist.iter().for_each(|line| builder.push(line.as_bytes()));
+        let sidecar = builder.finish(0);
+        let trigrams = TrigramIndex::new(&sidecar).unwrap();
+
+        // The uppercase É isn't indexed : only the ASCII trigrams select the blocks
+        assert_eq!(trigrams.candidates("BÉBÉ".as_bytes(), true).unwrap(), None);
+        assert_eq!(trigrams.candidates("PHOTOS/BÉBÉ".as_bytes(), true).unwrap(), Some(vec![0]));
+        assert_eq!(trigrams.candidates("bébé".as_bytes(), false).unwrap(), Some(vec![0]));
+        assert_eq!(trigrams.candidates("BÉBÉ".as_bytes(), false).unwrap(), Some(vec![]));
+    }
+
+    #[test]
+    fn bad_sidecar_err() {
+        assert!(matches!(TrigramIndex::new(b"FRTRIGRX"), Err(FrError::BadHeader)));
+
+        let mut sidecar = TrigramBuilder::new(4).finish(0);
+        sidecar[28] = 1; // one trigram, but no table
+        assert!(matches!(TrigramIndex::new(&sidecar), Err(FrError::Truncated { .. })));
+    }
+}
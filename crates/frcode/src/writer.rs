@@ -0,0 +1,228 @@
+use {
+    crate::{sort::Sorter, Encoder, Format, FrError, Kind, Metadata, Records, Report, Stat},
+    std::io::Write,
+};
+
+/// Compress the entries as they are pushed, straight into `W`
+pub struct FrWriter<W: Write> {
+    writer: W,
+    encoder: Encoder,
+    out_bytes: Vec<u8>,
+    sorter: Option<Sorter>,
+}
+
+impl<W: Write> FrWriter<W> {
+    pub fn new(writer: W) -> FrWriter<W> {
+        FrWriter::with_format(writer, Format::default())
+    }
+
+    pub fn with_format(writer: W, format: Format) -> FrWriter<W> {
+        FrWriter {
+            writer,
+            encoder: Encoder::new(format, Records::Paths),
+            out_bytes: Vec::with_capacity(1_000),
+            sorter: None,
+        }
+    }
+
+    /// Store the metadata of each entry, in the LOCATEW format. Push the entries with `push_stat`.
+    pub fn with_stats(writer: W) -> FrWriter<W> {
+        FrWriter {
+            writer,
+            encoder: Encoder::new(Format::LocateW, Records::Stats),
+            out_bytes: Vec::with_capacity(1_000),
+            sorter: None,
+        }
+    }
+
+    /// Store the kind of each entry, in the LOCATEW format
+    pub fn with_kinds(writer: W) -> FrWriter<W> {
+        FrWriter {
+            writer,
+            encoder: Encoder::new(Format::LocateW, Records::Kinds),
+            out_bytes: Vec::with_capacity(1_000),
+            sorter: None,
+        }
+    }
+
+    /// See `FrCompress::sync_every`
+    pub fn sync_every(mut self, interval: u32) -> FrWriter<W> {
+        self.encoder.sync_interval = (interval > 0).then_some(interval);
+        self
+    }
+
+    /// Sort the entries in byte order before compressing them, holding at most about `max_memory` bytes of entries
+    /// in memory and the rest in temporary files. Duplicate paths are kept once. Nothing is written until `finish`.
+    pub fn sorted(mut self, max_memory: usize) -> FrWriter<W> {
+        self.sorter = Some(Sorter::new(self.encoder.records, max_memory));
+        self
+    }
+
+    /// See `FrCompress::compressed`
+    pub fn compressed(mut self, level: i32) -> FrWriter<W> {
+        self.encoder.compress_blocks(level);
+        self
+    }
+
+    /// Describe the database in its footer. The number of entries, dirs and files are filled in by the writer,
+    /// starting from 0 whatever `metadata` holds.
+    pub fn with_metadata(mut self, metadata: Metadata) -> FrWriter<W> {
+        self.encoder.metadata = Some(Metadata {
+            dirs: 0,
+            files: 0,
+            files_bytes: 0,
+            ..metadata
+        });
+        self
+    }
+
+    /// The metadata to be written by `finish`
+    pub fn metadata_mut(&mut self) -> Option<&mut Metadata> {
+        self.encoder.metadata.as_mut()
+    }
+
+    /// How well the entries compressed so far. The report is also stored with the metadata by `finish`.
+    pub fn report(&self) -> &Report {
+        &self.encoder.report
+    }
+
+    /// Push an entry, its kind is stored if the writer was created `with_kinds`
+    pub fn push(&mut self, path: &[u8], kind: Kind) -> Result<(), FrError> {
+        self.push_record(path, kind, None)
+    }
+
+    /// Push an entry along with its metadata, stored if the writer was created `with_stats`
+    pub fn push_stat(&mut self, path: &[u8], stat: Stat) -> Result<(), FrError> {
+        self.push_record(path, stat.kind, Some(stat))
+    }
+
+    fn push_record(&mut self, path: &[u8], kind: Kind, stat: Option<Stat>) -> Result<(), FrError> {
+        match &mut self.sorter {
+            Some(sorter) => sorter.push(path, kind, stat),
+            None => self.write_record(path, kind, stat),
+        }
+    }
+
+    fn write_record(&mut self, path: &[u8], kind: Kind, stat: Option<Stat>) -> Result<(), FrError> {
+        self.out_bytes.clear();
+        self.encoder.entry(&mut self.out_bytes, path, kind, stat)?;
+        self.writer.write_all(&self.out_bytes)?;
+
+        if let Some(metadata) = &mut self.encoder.metadata {
+            if kind == Kind::Dir {
+                metadata.dirs += 1;
+            } else {
+                metadata.files += 1;
+                metadata.files_bytes += path.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the footer if any and flush. Nothing is written for an empty database without footer.
+    pub fn finish(mut self) -> Result<W, FrError> {
+        if let Some(sorter) = self.sorter.take() {
+            for sorted in sorter.sorted()? {
+                let (entry, stat) = sorted?;
+                self.write_record(&entry.path, entry.kind.unwrap_or_default(), stat)?;
+            }
+        }
+        if let Some(footer) = self.encoder.footer()? {
+            self.writer.write_all(&footer)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrCompress, FrDecompressBytes, SyncIndex},
+        std::io::Cursor,
+    };
+
+    #[test]
+    fn same_as_compress_ok() {
+        let dirlist = (0..300).map(|i| format!("/opt/{:02}/{}", i / 9, i)).collect::<Vec<String>>();
+
+        for format in [Format::LocateW, Format::Locate02] {
+            let mut writer = FrWriter::with_format(Vec::new(), format)
+                .sync_every(32)
+                .with_metadata(Metadata::default());
+            for (i, line) in dirlist.iter().enumerate() {
+                writer.push(line.as_bytes(), if i % 9 == 0 { Kind::Dir } else { Kind::File }).unwrap();
+            }
+            let db = writer.finish().unwrap();
+
+            let mut compressed_lines = FrCompress::with_format(Cursor::new(dirlist.join("\n")), format).sync_every(32);
+            let metadata = Metadata {
+                dirs: 34,
+                files: 266,
+                files_bytes: dirlist
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| i % 9 != 0)
+                    .map(|(_, line)| line.len() as u64)
+                    .sum(),
+                ..Default::default()
+            };
+            compressed_lines = compressed_lines.with_metadata(metadata.clone());
+            let expected = compressed_lines.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(db, expected.concat());
+            let report = Some(compressed_lines.report().clone());
+            assert_eq!(
+                Metadata::read(&mut Cursor::new(&db)).unwrap(),
+                Some(Metadata {
+                    entries: 300,
+                    report,
+                    ..metadata
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn stats_ok() {
+        let mut writer = FrWriter::with_stats(Vec::new()).sync_every(2);
+        let stat = Stat {
+            kind: Kind::Symlink,
+            size: 42,
+            mtime: 1_700_000_000,
+        };
+        for line in ["/a", "/a/b", "/a/c"] {
+            writer.push_stat(line.as_bytes(), stat).unwrap();
+        }
+        let db = writer.finish().unwrap();
+
+        assert_eq!(SyncIndex::read(&mut Cursor::new(&db)).unwrap().unwrap().records, Records::Stats);
+        let mut decompressed_lines = FrDecompressBytes::new(Cursor::new(&db));
+        assert_eq!(decompressed_lines.nth(2).unwrap().unwrap(), b"/a/c");
+        assert_eq!(decompressed_lines.stat(), Some(stat));
+
+        assert!(FrWriter::new(Vec::new()).finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sorted_ok() {
+        let mut writer = FrWriter::with_kinds(Vec::new()).sorted(100).with_metadata(Metadata::default());
+        for (path, kind) in [
+            ("/b/c", Kind::File),
+            ("/a", Kind::Dir),
+            ("/b", Kind::Dir),
+            ("/a", Kind::File),
+            ("/a/d", Kind::File),
+        ] {
+            writer.push(path.as_bytes(), kind).unwrap();
+        }
+        let db = writer.finish().unwrap();
+
+        let entries = FrDecompressBytes::new(Cursor::new(&db)).entries().collect::<Result<Vec<_>, _>>().unwrap();
+        let entries = entries.into_iter().map(|entry| (entry.path, entry.kind.unwrap())).collect::<Vec<_>>();
+        let expected = [("/a", Kind::Dir), ("/a/d", Kind::File), ("/b", Kind::Dir), ("/b/c", Kind::File)];
+        assert_eq!(entries, expected.map(|(path, kind)| (path.as_bytes().to_vec(), kind)));
+        let metadata = Metadata::read(&mut Cursor::new(&db)).unwrap().unwrap();
+        assert_eq!((metadata.entries, metadata.dirs, metadata.files), (4, 2, 2));
+    }
+}
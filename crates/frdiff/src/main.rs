@@ -0,0 +1,52 @@
+use {
+    clap::{value_parser, Arg, ArgAction, Command},
+    frcode::{Change, FrDiff},
+    serde_json::json,
+    std::error::Error,
+    std::fs::File,
+    std::io::{stdout, BufReader, BufWriter, Write},
+    std::path::PathBuf,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Command::new("frdiff")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("list the paths added and removed between two databases sorted in byte order")
+        .arg(
+            Arg::new("json")
+                .help("print a JSON object per line")
+                .short('j')
+                .long("json")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(Arg::new("old").required(true).value_parser(value_parser!(PathBuf)))
+        .arg(Arg::new("new").required(true).value_parser(value_parser!(PathBuf)))
+        .get_matches();
+
+    let is_json: bool = *matches.get_one("json").unwrap();
+    let open = |arg| -> Result<BufReader<File>, Box<dyn Error>> {
+        let path: &PathBuf = matches.get_one(arg).unwrap();
+        Ok(BufReader::new(File::open(path).map_err(|e| format!("{}: {e}", path.display()))?))
+    };
+    let changes = FrDiff::new(open("old")?, open("new")?);
+
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for change in changes {
+        let (status, path) = match change? {
+            Change::Added(path) => ("added", path),
+            Change::Removed(path) => ("removed", path),
+        };
+        if is_json {
+            let path = String::from_utf8_lossy(&path);
+            writeln!(out, "{}", json!({ "status": status, "path": path }))?;
+        } else {
+            out.write_all(if status == "added" { b"+ " } else { b"- " })?;
+            out.write_all(&path)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
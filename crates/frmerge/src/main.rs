@@ -0,0 +1,76 @@
+use {
+    clap::{builder::ValueRange, value_parser, Arg, ArgAction, Command},
+    frcode::{merge, Metadata},
+    num_format::{Locale, ToFormattedString},
+    std::error::Error,
+    std::fs::{remove_file, rename, File},
+    std::io::{BufReader, BufWriter},
+    std::path::PathBuf,
+    std::time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Command::new("frmerge")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("merge databases sorted in byte order into one, without duplicates")
+        .arg(
+            Arg::new("output")
+                .help("merged database")
+                .short('o')
+                .long("output")
+                .required(true)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("database")
+                .required(true)
+                .num_args(ValueRange::new(1..))
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .get_matches();
+
+    let start = Instant::now();
+    let output: &PathBuf = matches.get_one("output").unwrap();
+    let databases = matches.get_many::<PathBuf>("database").unwrap().collect::<Vec<_>>();
+
+    // The roots of the merged database are those of its inputs
+    let mut stats = Metadata {
+        created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        tool: format!("frmerge {}", env!("CARGO_PKG_VERSION")),
+        ..Default::default()
+    };
+    let mut inputs = Vec::with_capacity(databases.len());
+    for database in databases {
+        let mut file = File::open(database).map_err(|e| format!("{}: {e}", database.display()))?;
+        for root in Metadata::read(&mut file)?.map(|metadata| metadata.roots).unwrap_or_default() {
+            if !stats.roots.contains(&root) {
+                stats.roots.push(root);
+            }
+        }
+        inputs.push(BufReader::new(File::open(database)?));
+    }
+
+    // Write next to the output, in case it's also an input
+    let mut output1 = output.clone().into_os_string();
+    output1.push("1");
+    let writer = BufWriter::new(File::create(&output1)?);
+    let entries = match merge(inputs, writer, Some(stats)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            remove_file(&output1)?;
+            return Err(e.into());
+        }
+    };
+    rename(&output1, output)?;
+
+    let elapsed = start.elapsed();
+    println!(
+        "{} entrées fusionnées en {}.{:03} s",
+        entries.to_formatted_string(&Locale::fr_CA),
+        elapsed.as_secs(),
+        elapsed.subsec_millis()
+    );
+    Ok(())
+}
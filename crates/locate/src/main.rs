@@ -2,19 +2,257 @@ use clap::parser::ValueSource;
 
 use {
     clap::{builder::ValueRange, value_parser, Arg, ArgAction, Command},
-    frcode::FrDecompress,
-    globset::{Candidate, GlobBuilder, GlobSetBuilder},
+    frcode::{
+        base_name, to_os_str, verify, Entry, Format, FrDecompressSlice, FrError, Kind, Metadata, MlocateReader, PlocateReader, Records, Stat,
+        SyncIndex, TrigramIndex,
+    },
+    globset::{Candidate, GlobBuilder, GlobSet, GlobSetBuilder},
+    memmap2::Mmap,
     num_format::{Locale, ToFormattedString},
-    serde_json::Value,
+    std::collections::BTreeMap,
     std::env,
     std::error::Error,
     std::fs::File,
-    std::io::{stdout, BufReader, BufWriter, Write},
+    std::io::{self, stdout, BufReader, BufWriter, Cursor, Read, Write},
+    std::num::NonZeroUsize,
+    std::ops::Range,
+    std::path::{Path, PathBuf},
+    std::sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     std::sync::mpsc,
     std::thread,
+    std::time::{SystemTime, UNIX_EPOCH},
 };
 
-const PAS_DE_BD: &str = "La base de données est inexistante. Exécuter updatedb.exe";
+const PAS_DE_BD: &str = "La base de données est inexistante. Exécuter updatedb";
+const PAS_DE_STATS: &str = "La base de données ne contient pas de statistiques. Exécuter updatedb";
+const PAS_DE_META: &str = "La base de données ne contient pas la taille ni la date des entrées. Exécuter updatedb";
+const PAS_FRCODE: &str = "La base de données a été générée par mlocate ou plocate, sans statistiques ni sommes de contrôle";
+/// The databases of mlocate and plocate, when locate.db doesn't exist
+const FOREIGN_DBS: [&str; 2] = ["/var/lib/plocate/plocate.db", "/var/lib/mlocate/mlocate.db"];
+const BLOCKS_PER_TASK: usize = 32;
+const BATCH_LEN: usize = 8192;
+/// Bound of --newer and --older, so the cutoff in seconds can't overflow
+const MAX_DAYS: u64 = 1_000_000;
+
+/// The entries of a database written by mlocate or plocate
+type ForeignReader<'a> = Box<dyn Iterator<Item = Result<Entry, FrError>> + Send + 'a>;
+
+/// A found entry, with its metadata if the database stores it
+type Found = (Vec<u8>, Option<Stat>);
+
+/// Bounds on the size and the modification time of the entries
+#[derive(Default)]
+struct Filter {
+    larger: Option<u64>,
+    smaller: Option<u64>,
+    newer: Option<i64>,
+    older: Option<i64>,
+}
+
+impl Filter {
+    fn is_active(&self) -> bool {
+        self.larger.is_some() || self.smaller.is_some() || self.newer.is_some() || self.older.is_some()
+    }
+
+    fn is_match(&self, stat: Option<Stat>) -> bool {
+        let Some(stat) = stat else {
+            return !self.is_active();
+        };
+        self.larger.is_none_or(|larger| stat.size > larger)
+            && self.smaller.is_none_or(|smaller| stat.size < smaller)
+            && self.newer.is_none_or(|newer| stat.mtime > newer)
+            && self.older.is_none_or(|older| stat.mtime < older)
+    }
+}
+
+struct Matcher {
+    gs: GlobSet,
+    glob_count: usize,
+    is_all: bool,
+    is_base: bool,
+    filter: Filter,
+    /// The LOCATEW databases of paths written before the kinds were stored end the dir entries with a \
+    is_legacy: bool,
+}
+
+impl Matcher {
+    /// The entry to output if it matches the patterns and the filter
+    fn is_match<'e>(&self, entry: &'e [u8], kind: Option<Kind>, stat: Option<Stat>) -> Option<&'e [u8]> {
+        if !self.filter.is_match(stat) {
+            return None;
+        }
+
+        let (entry, kind) = match entry.strip_suffix(b"\\") {
+            Some(dir) if self.is_legacy => (dir, Some(Kind::Dir)),
+            _ => (entry, kind),
+        };
+        let name = if self.is_base {
+            if kind == Some(Kind::Dir) {
+                return None; // no need to match on a dir entry
+            }
+            base_name(entry)
+        } else {
+            entry
+        };
+        let name = to_os_str(name);
+        let candidate = Candidate::new(Path::new(&*name));
+
+        let is_match = if self.glob_count == 1 || !self.is_all {
+            self.gs.is_match_candidate(&candidate)
+        } else {
+            self.gs.matches_candidate(&candidate).len() == self.glob_count
+        };
+        is_match.then_some(entry)
+    }
+}
+
+/// Modification time as "YYYY-MM-DD HH:MM" UTC
+fn date(mtime: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, secs) = (mtime.div_euclid(86_400), mtime.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}-{month:02}-{day:02} {:02}:{:02}", secs / 3_600, secs % 3_600 / 60)
+}
+
+/// The literal parts of a glob pattern, which any matching entry contains
+fn literals(pattern: &str) -> Vec<&str> {
+    let mut literals = Vec::new();
+    let (mut start, mut braces, mut class) = (0, 0, None);
+    for (i, c) in pattern.char_indices() {
+        if let Some(open) = class {
+            // A ] right after [, [! or [^ is part of the class
+            let is_first = i == open + 1 || (i == open + 2 && matches!(pattern.as_bytes()[open + 1], b'!' | b'^'));
+            if c == ']' && !is_first {
+                class = None;
+                start = i + 1;
+            }
+            continue;
+        }
+
+        match c {
+            '*' | '?' | '[' | '{' if braces == 0 => {
+                if start < i {
+                    literals.push(&pattern[start..i]);
+                }
+                start = i + 1;
+            }
+            '}' if braces == 1 => start = i + 1,
+            _ => (),
+        }
+        match c {
+            '[' => class = Some(i),
+            '{' => braces += 1,
+            '}' if braces > 0 => braces -= 1,
+            _ => (),
+        }
+    }
+    if braces == 0 && class.is_none() && start < pattern.len() {
+        literals.push(&pattern[start..]);
+    }
+    literals
+}
+
+/// Where updatedb writes the database : next to the temporary directory on Windows, /var/lib/locate on Unix
+fn data_dir() -> PathBuf {
+    if cfg!(windows) {
+        let mut dir = env::temp_dir();
+        dir.pop();
+        dir
+    } else {
+        PathBuf::from("/var/lib/locate")
+    }
+}
+
+fn intersect(a: Vec<u32>, b: &[u32]) -> Vec<u32> {
+    a.into_iter().filter(|block| b.binary_search(block).is_ok()).collect()
+}
+
+/// The blocks that may contain entries matching the patterns. `None` if they must all be searched.
+fn candidate_blocks(trigrams: &TrigramIndex, patterns: &[Vec<&str>], is_all: bool, is_case: bool) -> Result<Option<Vec<u32>>, FrError> {
+    let mut candidates: Option<Vec<u32>> = None;
+    for literals in patterns {
+        let mut pattern_blocks: Option<Vec<u32>> = None;
+        for literal in literals {
+            if let Some(blocks) = trigrams.candidates(literal.as_bytes(), !is_case)? {
+                pattern_blocks = Some(match pattern_blocks {
+                    Some(pattern_blocks) => intersect(pattern_blocks, &blocks),
+                    None => blocks,
+                });
+            }
+        }
+
+        candidates = match (candidates, pattern_blocks) {
+            (candidates, None) if is_all => candidates,
+            (_, None) => return Ok(None), // this pattern could match anywhere
+            (None, Some(blocks)) => Some(blocks),
+            (Some(candidates), Some(blocks)) if is_all => Some(intersect(candidates, &blocks)),
+            (Some(mut candidates), Some(blocks)) => {
+                candidates.extend(blocks);
+                candidates.sort_unstable();
+                candidates.dedup();
+                Some(candidates)
+            }
+        };
+    }
+    Ok(candidates)
+}
+
+/// Runs of at most BLOCKS_PER_TASK consecutive blocks, among the candidates if any
+fn tasks(index: &SyncIndex, candidates: Option<&[u32]>) -> Vec<Range<usize>> {
+    let runs: Vec<Range<usize>> = match candidates {
+        Some(candidates) => candidates
+            .chunk_by(|a, b| a + 1 == *b)
+            .map(|run| run[0] as usize..run[run.len() - 1] as usize + 1)
+            .collect(),
+        None => std::iter::once(0..index.blocks()).collect(),
+    };
+    runs.into_iter()
+        .flat_map(|run| {
+            run.clone()
+                .step_by(BLOCKS_PER_TASK)
+                .map(move |start| start..(start + BLOCKS_PER_TASK).min(run.end))
+        })
+        .collect()
+}
+
+fn search_blocks(db: &[u8], index: &SyncIndex, blocks: Range<usize>, matcher: &Matcher) -> Result<Vec<Found>, FrError> {
+    index.check_blocks(db, blocks.clone())?;
+    let mut entries = blocks.len() * index.interval as usize;
+    let mut decompressed_entries = FrDecompressSlice::at_sync_point(db, index, blocks.start)?;
+
+    let mut found = Vec::new();
+    decompressed_entries.for_each_entry(|entry, kind, stat| {
+        found.extend(matcher.is_match(entry, kind, stat).map(|entry_out| (entry_out.to_vec(), stat)));
+        entries -= 1;
+        entries > 0
+    })?;
+    Ok(found)
+}
+
+/// Whether the database was written by mlocate or plocate
+fn is_foreign(db: &Path) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(8);
+    File::open(db)?.take(8).read_to_end(&mut magic)?;
+    Ok([MlocateReader::MAGIC, PlocateReader::MAGIC].contains(&magic.as_slice()))
+}
+
+fn foreign_reader(db: &[u8]) -> Result<Option<ForeignReader<'_>>, FrError> {
+    Ok(if db.starts_with(MlocateReader::MAGIC) {
+        Some(Box::new(MlocateReader::new(db)?))
+    } else if db.starts_with(PlocateReader::MAGIC) {
+        Some(Box::new(PlocateReader::new(db)?))
+    } else {
+        None
+    })
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = Command::new("locate")
@@ -26,6 +264,21 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .long("statistics")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("verify")
+                .help("don't search for entries, check the integrity of database")
+                .long("verify")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("database")
+                .help("search DBPATH instead of locate.db, which may be a mlocate or plocate database")
+                .short('d')
+                .long("database")
+                .value_name("DBPATH")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf)),
+        )
         .arg(
             Arg::new("all")
                 .help("only print entries that match all patterns")
@@ -62,37 +315,121 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .action(ArgAction::Set)
                 .value_parser(value_parser!(usize)),
         )
+        .arg(
+            Arg::new("larger")
+                .help("only entries larger than BYTES")
+                .long("larger")
+                .value_name("BYTES")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("smaller")
+                .help("only entries smaller than BYTES")
+                .long("smaller")
+                .value_name("BYTES")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("newer")
+                .help("only entries modified less than DAYS days ago")
+                .long("newer")
+                .value_name("DAYS")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64).range(..=MAX_DAYS)),
+        )
+        .arg(
+            Arg::new("older")
+                .help("only entries modified more than DAYS days ago")
+                .long("older")
+                .value_name("DAYS")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64).range(..=MAX_DAYS)),
+        )
+        .arg(
+            Arg::new("long")
+                .help("print the size and the modification time of entries")
+                .short('L')
+                .long("long")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("pattern")
-                .required_unless_present("stats")
+                .required_unless_present_any(["stats", "verify"])
                 .num_args(ValueRange::new(1..))
                 .action(ArgAction::Append),
         )
         .get_matches();
 
+    let db = match matches.get_one::<PathBuf>("database") {
+        Some(db) => db.clone(),
+        None => {
+            let mut db = data_dir().join("locate.db");
+            if !db.is_file() {
+                db = FOREIGN_DBS.iter().map(PathBuf::from).find(|db| db.is_file()).unwrap_or(db);
+            }
+            db
+        }
+    };
+    if !db.is_file() {
+        return Err(PAS_DE_BD.into());
+    }
+    let db_name = db.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
     let loc = &Locale::fr_CA;
-    if *matches.get_one("stats").unwrap() {
-        let mut stat = env::temp_dir();
-        stat.set_file_name("locate.txt");
-        if !stat.is_file() {
-            return Err(PAS_DE_BD.into());
+    let is_verify: bool = *matches.get_one("verify").unwrap();
+    let is_stats: bool = *matches.get_one("stats").unwrap();
+    if (is_verify || is_stats) && is_foreign(&db)? {
+        return Err(PAS_FRCODE.into());
+    }
+
+    if is_verify {
+        let verified = verify(&mut BufReader::new(File::open(&db)?))?;
+        println!(
+            "Base de données {db_name} intacte : {} entrées",
+            verified.entries.to_formatted_string(loc)
+        );
+        if !verified.checksums {
+            println!("      sans sommes de contrôle, seules les entrées ont été vérifiées");
         }
-        let reader = BufReader::new(File::open(stat)?);
-        let stats: Value = serde_json::from_reader(reader)?;
-        let dirs = stats["dirs"].as_u64().unwrap_or(0);
-        let files = stats["files"].as_u64().unwrap_or(0);
-        let files_bytes = stats["files_bytes"].as_u64().unwrap_or(0);
-        let db_size = stats["db_size"].as_u64().unwrap_or(0);
-        let elapsed = stats["elapsed"].as_u64().unwrap_or(0);
-        println!("Base de données locate.db :");
-        println!("      {} répertoires", dirs.to_formatted_string(loc));
-        println!("      {} fichiers", files.to_formatted_string(loc));
-        println!("      {} octets dans les noms de fichier", files_bytes.to_formatted_string(loc));
+        return Ok(());
+    }
+
+    if is_stats {
+        let mut db_file = File::open(&db)?;
+        let db_size = db_file.metadata()?.len();
+        let stats = Metadata::read(&mut db_file)?.ok_or(PAS_DE_STATS)?;
+        let age = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs().saturating_sub(stats.created) / 60;
+        println!("Base de données {db_name} :");
+        println!("      {} répertoires", stats.dirs.to_formatted_string(loc));
+        println!("      {} fichiers", stats.files.to_formatted_string(loc));
+        println!("      {} octets dans les noms de fichier", stats.files_bytes.to_formatted_string(loc));
         println!(
             "      {} octets utilisés pour stocker la base de données",
             db_size.to_formatted_string(loc)
         );
-        println!("      {} min {} sec pour générer la base de données", elapsed / 60, elapsed % 60);
+        println!(
+            "      {} min {} sec pour générer la base de données",
+            stats.elapsed / 60,
+            stats.elapsed % 60
+        );
+        println!("      générée par {} il y a {} h {} min", stats.tool, age / 60, age % 60);
+        println!("      racines : {}", stats.roots.join(" "));
+        if let Some(report) = &stats.report {
+            println!(
+                "      {} octets avant compression, ratio de {:.1} %",
+                report.raw_bytes.to_formatted_string(loc),
+                report.ratio()
+            );
+            println!("      préfixe commun de {:.1} octets en moyenne", report.mean_prefix_len());
+            println!(
+                "      {} comptes sur 2 octets, {} sur 4 octets",
+                report.escapes_2b.to_formatted_string(loc),
+                report.escapes_4b.to_formatted_string(loc)
+            );
+            println!("      entrée la plus longue : {} octets", report.longest.len().to_formatted_string(loc));
+        }
         return Ok(());
     }
 
@@ -107,33 +444,22 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(()); // nothing to do
     }
 
-    let mut db = env::temp_dir();
-    db.set_file_name("locate.db");
-    if !db.is_file() {
-        return Err(PAS_DE_BD.into());
-    }
-    let db_file = File::open(db)?;
-
-    // run the FrDecompress iterator on his own thread
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(move || {
-        let decompressed_entries = FrDecompress::new(BufReader::new(db_file));
-        for entry in decompressed_entries {
-            if let Err(e) = tx.send(entry.unwrap()) {
-                if !is_limit {
-                    eprintln!("{e}");
-                }
-                break;
-            }
-        }
-    });
-
     let is_all: bool = *matches.get_one("all").unwrap();
     let is_base: bool = *matches.get_one("base").unwrap();
     let is_case: bool = *matches.get_one("case").unwrap();
+    let is_long: bool = *matches.get_one("long").unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let days_ago = |arg| matches.get_one::<u64>(arg).map(|days| now - (*days as i64) * 86_400);
+    let filter = Filter {
+        larger: matches.get_one("larger").copied(),
+        smaller: matches.get_one("smaller").copied(),
+        newer: days_ago("newer"),
+        older: days_ago("older"),
+    };
     let patterns = matches.get_many("pattern").unwrap().collect::<Vec<&String>>();
 
     let mut gs_builder = GlobSetBuilder::new();
+    let mut globs = Vec::with_capacity(patterns.len());
     for pattern in patterns {
         let pattern = if let Some(pattern) = pattern.strip_prefix('/') {
             pattern.to_owned() // pattern «as is»
@@ -150,49 +476,171 @@ fn main() -> Result<(), Box<dyn Error>> {
             .build()?;
 
         gs_builder.add(g_builder);
+        globs.push(pattern);
+    }
+
+    let db_file = File::open(&db)?;
+    // Safety : updatedb replaces locate.db with a rename, the mapped file is never modified
+    let db_map = unsafe { Mmap::map(&db_file)? };
+    let db_map: &[u8] = &db_map;
+    let foreign = foreign_reader(db_map)?;
+    let layout = match foreign {
+        Some(_) => None,
+        None => {
+            let decompressed_entries = FrDecompressSlice::new(db_map)?;
+            Some((decompressed_entries.format(), decompressed_entries.records()))
+        }
+    };
+    if (is_long || filter.is_active()) && layout.is_none_or(|(_, records)| records != Records::Stats) {
+        return Err(PAS_DE_META.into());
     }
 
     let gs = gs_builder.build()?;
     let glob_count = gs.len();
+    let matcher = Matcher {
+        gs,
+        glob_count,
+        is_all,
+        is_base,
+        filter,
+        is_legacy: layout == Some((Format::LocateW, Records::Paths)),
+    };
+    let index = match foreign {
+        Some(_) => None,
+        None => SyncIndex::read(&mut Cursor::new(db_map))?,
+    };
+
+    // Only search the blocks where the trigram sidecar of the database finds the literal parts of the patterns
+    let tri = db.with_extension("tri");
+    let tri_map = match (&index, File::open(&tri)) {
+        // Safety : updatedb replaces locate.tri with a rename, the mapped file is never modified
+        (Some(_), Ok(tri_file)) => Some(unsafe { Mmap::map(&tri_file)? }),
+        _ => None,
+    };
+    let candidates = match (&index, tri_map.as_deref().map(TrigramIndex::new)) {
+        (Some(index), Some(Ok(trigrams))) if trigrams.is_for(db_map.len() as u64, index) => {
+            let patterns = globs.iter().map(|glob| literals(glob)).collect::<Vec<_>>();
+            candidate_blocks(&trigrams, &patterns, is_all, is_case)?
+        }
+        _ => None, // no sidecar, or a stale one
+    };
+    let tasks = index.as_ref().map(|index| tasks(index, candidates.as_deref())).unwrap_or_default();
 
     let stdout = stdout();
     let mut out = BufWriter::new(stdout.lock());
     let mut ctr: usize = 0;
+    let stop = AtomicBool::new(false);
+    let next_task = AtomicUsize::new(0);
 
-    for entry in rx {
-        // dir entries are terminated with a \
-        let (entry_out, is_dir) = match entry.strip_suffix('\\') {
-            Some(dir) => (dir, true),
-            None => (entry.as_str(), false),
-        };
+    thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+        // Each batch of found entries is tagged with its position in the database
+        let (tx, rx) = mpsc::channel::<(usize, Result<Vec<Found>, FrError>)>();
 
-        let candidate = if is_base {
-            if is_dir {
-                continue; // no need to match on a dir entry
+        match (&index, foreign) {
+            (Some(index), _) => {
+                // Decompress and match the blocks on all cores
+                let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get).min(tasks.len());
+                for _ in 0..workers {
+                    let tx = tx.clone();
+                    let (index, tasks, matcher, stop, next_task) = (index, &tasks, &matcher, &stop, &next_task);
+                    scope.spawn(move || {
+                        while !stop.load(Ordering::Relaxed) {
+                            let task = next_task.fetch_add(1, Ordering::Relaxed);
+                            let Some(blocks) = tasks.get(task) else {
+                                break;
+                            };
+                            let found = search_blocks(db_map, index, blocks.clone(), matcher);
+                            if tx.send((task, found)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
             }
-            Candidate::new(entry_out.rsplit_once('\\').unwrap().1) // basename
-        } else {
-            Candidate::new(entry_out)
-        };
-
-        if glob_count == 1 || !is_all {
-            if !gs.is_match_candidate(&candidate) {
-                continue;
+            (None, Some(mut foreign)) => {
+                // A mlocate or plocate database : no metadata, nor sync points
+                let (tx, matcher, stop) = (tx.clone(), &matcher, &stop);
+                scope.spawn(move || {
+                    let mut batch = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        let mut found = Vec::new();
+                        let mut decompressed = 0;
+                        for entry in foreign.by_ref().take(BATCH_LEN) {
+                            let entry = match entry {
+                                Ok(entry) => entry,
+                                Err(e) => {
+                                    let _ = tx.send((batch, Err(e)));
+                                    return;
+                                }
+                            };
+                            found.extend(
+                                matcher
+                                    .is_match(&entry.path, entry.kind, None)
+                                    .map(|entry_out| (entry_out.to_vec(), None)),
+                            );
+                            decompressed += 1;
+                        }
+                        if decompressed == 0 || tx.send((batch, Ok(found))).is_err() {
+                            break;
+                        }
+                        batch += 1;
+                    }
+                });
+            }
+            (None, None) => {
+                // No sync points : run the FrDecompress iterator on his own thread
+                let mut decompressed_entries = FrDecompressSlice::new(db_map)?;
+                let (tx, matcher, stop) = (tx.clone(), &matcher, &stop);
+                scope.spawn(move || {
+                    let mut batch = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        let mut found = Vec::new();
+                        let mut decompressed = 0;
+                        let result = decompressed_entries.for_each_entry(|entry, kind, stat| {
+                            found.extend(matcher.is_match(entry, kind, stat).map(|entry_out| (entry_out.to_vec(), stat)));
+                            decompressed += 1;
+                            decompressed < BATCH_LEN
+                        });
+                        if let Err(e) = result {
+                            let _ = tx.send((batch, Err(e)));
+                            break;
+                        }
+                        if decompressed == 0 || tx.send((batch, Ok(found))).is_err() {
+                            break;
+                        }
+                        batch += 1;
+                    }
+                });
             }
-        } else if gs.matches_candidate(&candidate).len() != glob_count {
-            continue;
         }
+        drop(tx);
 
-        if !is_count {
-            out.write_all(entry_out.as_bytes())?;
-            out.write_all(b"\n")?;
-        }
+        // Output the batches in database order
+        let mut pending = BTreeMap::new();
+        let mut next_batch = 0;
+        for (batch, found) in rx {
+            pending.insert(batch, found);
+            while let Some(found) = pending.remove(&next_batch) {
+                next_batch += 1;
+                for (entry_out, stat) in found? {
+                    if !is_count {
+                        if let Some(stat) = stat.filter(|_| is_long) {
+                            write!(out, "{:>17} {} ", stat.size.to_formatted_string(loc), date(stat.mtime))?;
+                        }
+                        out.write_all(&entry_out)?;
+                        out.write_all(b"\n")?;
+                    }
 
-        ctr += 1;
-        if is_limit && ctr == limit {
-            break;
+                    ctr += 1;
+                    if is_limit && ctr == limit {
+                        stop.store(true, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                }
+            }
         }
-    }
+        Ok(())
+    })?;
 
     if is_count {
         writeln!(out, "{}", ctr.to_formatted_string(loc))?;
@@ -201,3 +649,63 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_dirs_ok() {
+        let mut gs_builder = GlobSetBuilder::new();
+        gs_builder.add(GlobBuilder::new("*foo*").case_insensitive(true).build().unwrap());
+        let mut matcher = Matcher {
+            gs: gs_builder.build().unwrap(),
+            glob_count: 1,
+            is_all: false,
+            is_base: true,
+            filter: Filter::default(),
+            is_legacy: true,
+        };
+        // The trailing \ of a dir is stripped, and the dir isn't matched on its base name
+        assert_eq!(matcher.is_match(b"C:\\foo\\", None, None), None);
+        assert_eq!(matcher.is_match(b"C:\\foo\\x.foo", None, None), Some(&b"C:\\foo\\x.foo"[..]));
+        matcher.is_base = false;
+        assert_eq!(matcher.is_match(b"C:\\foo\\", None, None), Some(&b"C:\\foo"[..]));
+        matcher.is_legacy = false;
+        assert_eq!(matcher.is_match(b"C:\\foo\\", None, None), Some(&b"C:\\foo\\"[..]));
+    }
+
+    #[test]
+    fn date_ok() {
+        assert_eq!(date(0), "1970-01-01 00:00");
+        assert_eq!(date(951_827_696), "2000-02-29 12:34");
+        assert_eq!(date(1_700_000_000), "2023-11-14 22:13");
+        assert_eq!(date(-60), "1969-12-31 23:59");
+    }
+
+    #[test]
+    fn literals_ok() {
+        assert_eq!(literals("*rapport*"), ["rapport"]);
+        assert_eq!(literals("*Bébé?Aardvark.jp*g"), ["Bébé", "Aardvark.jp", "g"]);
+        assert_eq!(literals("C:\\[a-z]*\\{doc,xls}x"), ["C:\\", "\\", "x"]);
+        assert_eq!(literals("*[!]]x*"), ["x"]);
+        assert_eq!(literals("*{a[}]b,c}d"), ["d"]);
+        assert!(literals("**").is_empty());
+    }
+
+    #[test]
+    fn tasks_ok() {
+        let index = SyncIndex {
+            format: Default::default(),
+            records: Default::default(),
+            interval: 256,
+            offsets: vec![0; 70],
+            end: 0,
+            checksums: None,
+        };
+        assert_eq!(tasks(&index, None), [0..32, 32..64, 64..70]);
+        assert_eq!(tasks(&index, Some(&[3, 4, 5, 9])), [3..6, 9..10]);
+        assert_eq!(tasks(&index, Some(&(10..50).collect::<Vec<u32>>())), [10..42, 42..50]);
+        assert!(tasks(&index, Some(&[])).is_empty());
+    }
+}
@@ -1,152 +1,271 @@
 use {
-    frcode::compress_file,
-    serde_json::json,
+    clap::{value_parser, Arg, ArgAction, Command},
+    frcode::{
+        apply, Changes, FrDecompressBytes, FrWriter, Kind, Metadata, Stat, TrigramBuilder, DEFAULT_COMPRESSION_LEVEL, DEFAULT_SORT_MEMORY,
+        DEFAULT_SYNC_INTERVAL,
+    },
+    prune::Prune,
+    roots::{roots, walk},
     std::env,
     std::error::Error,
-    std::fs::{remove_file, rename, File},
-    std::io::{BufWriter, Write},
-    std::time::Instant,
-    walkdir::WalkDir,
-    windows::core::PCWSTR,
-    windows::Win32::{Storage::FileSystem::GetDriveTypeW, Storage::FileSystem::GetLogicalDrives},
+    std::fs::{self, remove_file, rename, File, Metadata as FsMetadata},
+    std::io::{self, BufReader, BufWriter},
+    std::path::{Path, PathBuf},
+    std::time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
-#[derive(Default)]
-struct Statistics {
-    dirs: usize,
-    files: usize,
-    files_bytes: usize,
-    db_size: usize,
-    elapsed: u64,
-}
+mod prune;
+mod roots;
 
-struct DwordBits {
-    dword: u32,
-    ctr: u8,
+fn stat(m: &FsMetadata) -> Stat {
+    let kind = if m.is_symlink() {
+        Kind::Symlink
+    } else if m.is_dir() {
+        Kind::Dir
+    } else if m.is_file() {
+        Kind::File
+    } else {
+        Kind::Other
+    };
+    let mtime = match m.modified().map(|t| t.duration_since(UNIX_EPOCH)) {
+        Ok(Ok(d)) => d.as_secs() as i64,
+        Ok(Err(e)) => -(e.duration().as_secs() as i64), // before 1970
+        Err(_) => 0,
+    };
+    Stat { kind, size: m.len(), mtime }
 }
 
-impl DwordBits {
-    fn new(dword: u32) -> DwordBits {
-        DwordBits { dword, ctr: 0 }
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Command::new("updatedb")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(
+            Arg::new("trigrams")
+                .help("also build the trigram index used by locate to speed up substring searches")
+                .short('t')
+                .long("trigrams")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sorted")
+                .help("sort the entries in byte order, as needed by frdiff and --update, with a bounded memory use")
+                .short('s')
+                .long("sorted")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("zstd")
+                .help("compress each block of the database with zstd, making it about three times smaller")
+                .short('z')
+                .long("zstd")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("prunepaths")
+                .help("directories not to walk, separated by spaces, instead of PRUNEPATHS in updatedb.conf")
+                .long("prunepaths")
+                .value_name("PATHS"),
+        )
+        .arg(
+            Arg::new("prunenames")
+                .help("names of the directories not to walk, such as \".git node_modules target\", instead of PRUNENAMES in updatedb.conf")
+                .long("prunenames")
+                .value_name("NAMES"),
+        )
+        .arg(
+            Arg::new("prunefs")
+                .help("types of the file systems not to walk, instead of PRUNEFS in updatedb.conf")
+                .long("prunefs")
+                .value_name("TYPES"),
+        )
+        .arg(
+            Arg::new("update")
+                .help("walk only these directories again, and update their entries in the existing database")
+                .short('u')
+                .long("update")
+                .value_name("DIR")
+                .conflicts_with_all(["sorted", "zstd"]) // the database keeps its order and its compression
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .get_matches();
+    let is_trigrams: bool = *matches.get_one("trigrams").unwrap();
+    let is_sorted: bool = *matches.get_one("sorted").unwrap();
+    let is_zstd: bool = *matches.get_one("zstd").unwrap();
+    let options = [("--trigrams", is_trigrams), ("--sorted", is_sorted), ("--zstd", is_zstd)]
+        .into_iter()
+        .filter(|(_, is_set)| *is_set)
+        .map(|(option, _)| option.to_owned())
+        .collect::<Vec<String>>();
+
+    // The rules of updatedb.conf, in /etc on Unix and next to the database on Windows, then those of the command line
+    let dir = data_dir();
+    let conf = if cfg!(windows) {
+        dir.join("updatedb.conf")
+    } else {
+        PathBuf::from("/etc/updatedb.conf")
+    };
+    let mut prune = Prune::read(&conf)?;
+    if let Some(paths) = matches.get_one::<String>("prunepaths") {
+        prune.set_paths(paths);
+    }
+    if let Some(names) = matches.get_one::<String>("prunenames") {
+        prune.set_names(names);
+    }
+    if let Some(fs) = matches.get_one::<String>("prunefs") {
+        prune.set_fs(fs);
     }
-}
 
-impl Iterator for DwordBits {
-    type Item = bool;
+    let start = Instant::now();
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let db = dir.join("locate.db");
+    let db1 = dir.join("locate.db1");
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.ctr == 32 {
-            return None;
+    if let Some(dirs) = matches.get_many::<PathBuf>("update") {
+        if db.is_file() {
+            update(&db, &db1, dirs.collect(), &prune, is_trigrams, start)?;
+            return finish(&db, &db1, is_trigrams);
         }
+    }
 
-        let bit = self.dword & (1 << self.ctr) != 0;
-        self.ctr += 1;
+    let roots = roots(&prune)?;
 
-        Some(bit)
+    // Compress the entries of each root as they are found, with the statistics in the footer
+    let stats = Metadata {
+        created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        tool: format!("updatedb {}", env!("CARGO_PKG_VERSION")),
+        roots: roots.iter().map(|root| root.to_string_lossy().into_owned()).collect(), // UTF-8 in the metadata
+        options,
+        ..Default::default()
+    };
+    let mut writer = FrWriter::with_stats(BufWriter::new(File::create(&db1)?))
+        .sync_every(DEFAULT_SYNC_INTERVAL)
+        .with_metadata(stats);
+    if is_sorted {
+        writer = writer.sorted(DEFAULT_SORT_MEMORY);
     }
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let start = Instant::now();
+    if is_zstd {
+        writer = writer.compressed(DEFAULT_COMPRESSION_LEVEL);
+    }
+    // The entries are pushed in the order of the database unless sorted
+    let mut trigrams = (is_trigrams && !is_sorted).then(|| TrigramBuilder::new(DEFAULT_SYNC_INTERVAL));
 
-    // Get the list of the fixed logical drives
-    let ld_bits: u32 = unsafe { GetLogicalDrives() };
-    if ld_bits == 0 {
-        return Err(match std::io::Error::last_os_error().raw_os_error() {
-            Some(e) => format!("GetLogicalDrives: {e}").into(),
-            None => "GetLogicalDrives: DOH!".into(),
-        });
-    }
-
-    let ld_fix = DwordBits::new(ld_bits)
-        .zip("ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars())
-        .filter_map(|(b, c)| {
-            if b {
-                let mut ld = String::with_capacity(3);
-                ld.push(c);
-                ld.push_str(":\\");
-                // Convert an UTF-8 string to a null-delimited UTF-16 string
-                let mut ld_utf16: Vec<u16> = ld.encode_utf16().collect();
-                ld_utf16.push(0);
-                let ld_type = unsafe { GetDriveTypeW(PCWSTR::from_raw(ld_utf16.as_mut_ptr())) };
-                if ld_type == 3 {
-                    Some(ld)
-                } else {
-                    None // not a fixed logical drive
-                }
-            } else {
-                None // not a logical drive
+    for entry in walk(&roots, &prune) {
+        if let Ok(m) = entry.metadata() {
+            let p = entry.path().as_os_str().as_encoded_bytes(); // raw path, may contain non-unicode sequence
+            writer.push_stat(p, stat(&m))?;
+            if let Some(trigrams) = &mut trigrams {
+                trigrams.push(p);
             }
-        })
-        .collect::<Vec<String>>();
+        }
+    }
+
+    if let Some(stats) = writer.metadata_mut() {
+        stats.elapsed = start.elapsed().as_secs();
+    }
+    drop(writer.finish()?);
+
+    if is_trigrams {
+        write_trigrams(&db1, trigrams)?;
+    }
+    finish(&db, &db1, is_trigrams)
+}
 
-    // Generate a dir list from each logical drives and save it to a temp file
-    let mut stats = Statistics::default();
-    let mut dirlist = env::temp_dir();
-    dirlist.set_file_name("dirlist.txt");
+/// The directory of the database : next to the temporary directory on Windows, that is %LOCALAPPDATA%,
+/// and /var/lib/locate on Unix. locate looks for the database there too.
+fn data_dir() -> PathBuf {
+    if cfg!(windows) {
+        let mut dir = env::temp_dir();
+        dir.pop();
+        dir
+    } else {
+        PathBuf::from("/var/lib/locate")
+    }
+}
 
-    let mut writer = BufWriter::new(File::create(&dirlist)?);
-    for ld in ld_fix {
-        let walker = WalkDir::new(ld).into_iter().filter_map(Result::ok);
-        for entry in walker {
+/// Walk `dirs` again and replace their entries in the database `db`, written to `db1`
+fn update(db: &Path, db1: &Path, dirs: Vec<&PathBuf>, prune: &Prune, is_trigrams: bool, start: Instant) -> Result<(), Box<dyn Error>> {
+    let roots = roots(prune)?;
+    let mut changes = Changes::new();
+    for dir in dirs {
+        let dir = &absolute(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        changes.replace(dir.as_os_str().as_encoded_bytes());
+        // The walk stays on the file system of `dir`, the roots mounted below it are walked too
+        let nested = roots.iter().filter(|root| root.starts_with(dir) && *root != dir);
+        let dir_roots = std::iter::once(dir)
+            .chain(nested)
+            .filter(|root| !prune.is_pruned_root(root))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        for entry in walk(&dir_roots, prune) {
             if let Ok(m) = entry.metadata() {
-                let p = entry.path().to_string_lossy(); // path may contain non-unicode sequence
-                if m.is_dir() {
-                    writeln!(writer, "{p}\\")?;
-                    stats.dirs += 1;
-                } else {
-                    writeln!(writer, "{p}")?;
-                    stats.files += 1;
-                    stats.files_bytes += p.len();
-                }
+                changes.add(entry.path().as_os_str().as_encoded_bytes(), stat(&m));
             }
         }
     }
-    writer.flush()?;
 
-    // Compress the dir list
-    let mut db1 = env::temp_dir();
-    db1.set_file_name("locate.db1");
-    stats.db_size = compress_file(&dirlist, &db1)?;
+    // Keep the roots of the database, and the options it was built with but --trigrams
+    let mut stats = Metadata::read(&mut File::open(db)?)?.unwrap_or_default();
+    stats.created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    stats.tool = format!("updatedb {}", env!("CARGO_PKG_VERSION"));
+    stats.options.retain(|option| option != "--trigrams");
+    if is_trigrams {
+        stats.options.push("--trigrams".to_owned());
+    }
+    stats.elapsed = start.elapsed().as_secs();
+    apply(BufReader::new(File::open(db)?), changes, BufWriter::new(File::create(db1)?), Some(stats))?;
 
-    // Cleanup
-    remove_file(&dirlist)?;
-    let mut db = env::temp_dir();
-    db.set_file_name("locate.db");
-    if db.is_file() {
-        remove_file(&db)?;
+    if is_trigrams {
+        write_trigrams(db1, None)?;
     }
-    rename(&db1, &db)?;
+    Ok(())
+}
 
-    // Output the statistics
-    stats.elapsed = start.elapsed().as_secs();
-    let stats = json!({
-        "dirs": stats.dirs,
-        "files": stats.files,
-        "files_bytes": stats.files_bytes,
-        "db_size": stats.db_size,
-        "elapsed": stats.elapsed,
-    });
-    let j = serde_json::to_string(&stats)?;
-    let mut path = env::temp_dir();
-    path.set_file_name("locate.txt");
-    let mut writer = BufWriter::new(File::create(path)?);
-    writer.write_all(j.as_bytes())?;
-    writer.flush()?;
+/// `dir` as the paths of the database, absolute and without `.` or `..`. On Windows, canonicalize would
+/// give a `\\?\` path, unlike the walk from the drive roots.
+fn absolute(dir: &Path) -> io::Result<PathBuf> {
+    if cfg!(windows) {
+        std::path::absolute(dir)
+    } else {
+        fs::canonicalize(dir)
+    }
+}
+
+/// The trigram index is tied to the database by its length. Without the entries pushed as they were written,
+/// they are read back from the database.
+fn write_trigrams(db1: &Path, trigrams: Option<TrigramBuilder>) -> Result<(), Box<dyn Error>> {
+    let trigrams = match trigrams {
+        Some(trigrams) => trigrams,
+        None => {
+            let mut trigrams = TrigramBuilder::new(DEFAULT_SYNC_INTERVAL);
+            for path in FrDecompressBytes::new(BufReader::new(File::open(db1)?)) {
+                trigrams.push(&path?);
+            }
+            trigrams
+        }
+    };
+    fs::write(db1.with_extension("tri1"), trigrams.finish(fs::metadata(db1)?.len()))?;
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Replace the database and its trigram index by the new ones
+fn finish(db: &Path, db1: &Path, is_trigrams: bool) -> Result<(), Box<dyn Error>> {
+    if db.is_file() {
+        remove_file(db)?;
+    }
+    rename(db1, db)?;
+    let tri = db.with_extension("tri");
+    if tri.is_file() {
+        remove_file(&tri)?;
+    }
+    if is_trigrams {
+        rename(db1.with_extension("tri1"), &tri)?;
+    }
 
-    #[test]
-    fn dwordbits_ok() {
-        let mut bits = DwordBits::new(12 as u32);
-        assert_eq!(bits.next(), Some(false));
-        assert_eq!(bits.next(), Some(false));
-        assert_eq!(bits.next(), Some(true));
-        assert_eq!(bits.next(), Some(true));
-        bits.for_each(|b| assert_eq!(b, false));
+    // The statistics used to be in a separate file
+    let stat = db.with_extension("txt");
+    if stat.is_file() {
+        remove_file(&stat)?;
     }
+    Ok(())
 }
@@ -0,0 +1,182 @@
+// The pruning rules of updatedb, in the style of updatedb.conf :
+//   PRUNEPATHS="/var/cache /home/me/tmp"
+//   PRUNENAMES=".git node_modules target"
+//   PRUNEFS="nfs tmpfs"
+// The values are separated by spaces. A variable missing from the file keeps its default.
+
+use {
+    std::error::Error,
+    std::ffi::OsString,
+    std::fs,
+    std::path::{Path, PathBuf},
+    walkdir::DirEntry,
+};
+
+/// Pseudo file systems, memory and network file systems
+const DEFAULT_PRUNEFS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "ceph",
+    "cgroup",
+    "cgroup2",
+    "cifs",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fuse.sshfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nfs",
+    "nfs4",
+    "nsfs",
+    "proc",
+    "pstore",
+    "ramfs",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "smb3",
+    "smbfs",
+    "sysfs",
+    "tmpfs",
+    "tracefs",
+];
+
+/// The internals of the version control systems
+const DEFAULT_PRUNENAMES: &[&str] = &[".bzr", ".git", ".hg", ".svn"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Prune {
+    /// Directories not walked, with everything below them
+    pub paths: Vec<PathBuf>,
+    /// Names of the directories not walked, wherever they are
+    pub names: Vec<OsString>,
+    /// Types of the file systems not walked, compared without case
+    pub fs: Vec<String>,
+}
+
+impl Default for Prune {
+    fn default() -> Self {
+        Prune {
+            paths: Vec::new(),
+            names: DEFAULT_PRUNENAMES.iter().map(OsString::from).collect(),
+            fs: DEFAULT_PRUNEFS.iter().map(|fs| fs.to_string()).collect(),
+        }
+    }
+}
+
+impl Prune {
+    /// The rules of an updatedb.conf file, the default ones if there's no such file
+    pub fn read(conf: &Path) -> Result<Prune, Box<dyn Error>> {
+        if !conf.is_file() {
+            return Ok(Prune::default());
+        }
+        let conf_text = fs::read_to_string(conf).map_err(|e| format!("{}: {e}", conf.display()))?;
+        Prune::parse(&conf_text).map_err(|e| format!("{}: {e}", conf.display()).into())
+    }
+
+    pub fn parse(conf: &str) -> Result<Prune, String> {
+        let mut prune = Prune::default();
+        for (i, line) in conf.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((variable, value)) = line.split_once('=') else {
+                return Err(format!("ligne {} : = attendu", i + 1));
+            };
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            match variable.trim() {
+                "PRUNEPATHS" => prune.set_paths(value),
+                "PRUNENAMES" => prune.set_names(value),
+                "PRUNEFS" => prune.set_fs(value),
+                _ => (), // such as PRUNE_BIND_MOUNTS
+            }
+        }
+        Ok(prune)
+    }
+
+    pub fn set_paths(&mut self, paths: &str) {
+        self.paths = paths.split_whitespace().map(PathBuf::from).collect();
+    }
+
+    pub fn set_names(&mut self, names: &str) {
+        self.names = names.split_whitespace().map(OsString::from).collect();
+    }
+
+    pub fn set_fs(&mut self, fs: &str) {
+        self.fs = fs.split_whitespace().map(str::to_owned).collect();
+    }
+
+    /// Whether the walk skips this entry and never descends into it
+    pub fn is_pruned(&self, entry: &DirEntry) -> bool {
+        entry.file_type().is_dir() && (self.names.iter().any(|name| name == entry.file_name()) || self.paths.iter().any(|path| path == entry.path()))
+    }
+
+    /// Whether a root, or a directory to walk again, is a pruned path or lies below one
+    pub fn is_pruned_root(&self, root: &Path) -> bool {
+        self.paths.iter().any(|path| root.starts_with(path))
+    }
+
+    pub fn is_pruned_fs(&self, fs_type: &str) -> bool {
+        self.fs.iter().any(|fs| fs.eq_ignore_ascii_case(fs_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::roots::walk,
+        std::{env, process},
+    };
+
+    #[test]
+    fn parse_ok() {
+        let conf = "\
+# Exclusions
+PRUNE_BIND_MOUNTS=\"yes\"
+PRUNEPATHS=\"/tmp /var/spool\"
+PRUNENAMES = node_modules  target
+";
+        let prune = Prune::parse(conf).unwrap();
+        assert_eq!(prune.paths, [Path::new("/tmp"), Path::new("/var/spool")]);
+        assert_eq!(prune.names, ["node_modules", "target"]);
+        assert_eq!(prune.fs, Prune::default().fs);
+        assert!(prune.is_pruned_fs("NFS") && !prune.is_pruned_fs("ext4"));
+        assert!(prune.is_pruned_root(Path::new("/tmp")) && prune.is_pruned_root(Path::new("/var/spool/cron")));
+        assert!(!prune.is_pruned_root(Path::new("/var")) && !prune.is_pruned_root(Path::new("/tmpfs")));
+
+        assert_eq!(Prune::parse("PRUNEFS=\"\"").unwrap().fs, Vec::<String>::new());
+        assert_eq!(Prune::parse("PRUNEFS").unwrap_err(), "ligne 1 : = attendu");
+        assert_eq!(Prune::read(Path::new("/nulle/part/updatedb.conf")).unwrap(), Prune::default());
+    }
+
+    #[test]
+    fn walk_ok() {
+        let root = env::temp_dir().join(format!("prune-{}", process::id()));
+        for dir in ["src/.git/objects", "src/node_modules/a", "target/debug", "docs", "cache/x"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("target.txt"), "").unwrap();
+        fs::write(root.join("docs/target"), "").unwrap(); // a file isn't pruned by its name
+
+        let mut prune = Prune::parse("PRUNENAMES=\".git node_modules target\"").unwrap();
+        prune.paths.push(root.join("cache"));
+        let mut paths = walk(std::slice::from_ref(&root), &prune)
+            .map(|entry| entry.path().strip_prefix(&root).unwrap().to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+        assert_eq!(paths, ["", "docs", "docs/target", "src", "target.txt"].map(PathBuf::from));
+
+        // The root itself
+        prune.paths.push(root.clone());
+        assert_eq!(walk(std::slice::from_ref(&root), &prune).count(), 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
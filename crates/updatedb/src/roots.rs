@@ -0,0 +1,225 @@
+// The roots indexed by updatedb : the fixed logical drives on Windows, the mount points of the local
+// file systems on Linux, and / on the other Unix systems.
+
+use {
+    crate::prune::Prune,
+    std::error::Error,
+    std::path::PathBuf,
+    walkdir::{DirEntry, WalkDir},
+};
+
+#[cfg(target_os = "linux")]
+use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+#[cfg(windows)]
+use windows::{
+    core::PCWSTR,
+    Win32::Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives, GetVolumeInformationW},
+};
+
+#[cfg(any(windows, test))]
+struct DwordBits {
+    dword: u32,
+    ctr: u8,
+}
+
+#[cfg(any(windows, test))]
+impl DwordBits {
+    fn new(dword: u32) -> DwordBits {
+        DwordBits { dword, ctr: 0 }
+    }
+}
+
+#[cfg(any(windows, test))]
+impl Iterator for DwordBits {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ctr == 32 {
+            return None;
+        }
+
+        let bit = self.dword & (1 << self.ctr) != 0;
+        self.ctr += 1;
+
+        Some(bit)
+    }
+}
+
+/// The fixed logical drives, such as `C:\`, whose file system isn't pruned
+#[cfg(windows)]
+fn mounted_roots(prune: &Prune) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let ld_bits: u32 = unsafe { GetLogicalDrives() };
+    if ld_bits == 0 {
+        return Err(match std::io::Error::last_os_error().raw_os_error() {
+            Some(e) => format!("GetLogicalDrives: {e}").into(),
+            None => "GetLogicalDrives: DOH!".into(),
+        });
+    }
+
+    let ld_fix = DwordBits::new(ld_bits)
+        .zip("ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars())
+        .filter_map(|(b, c)| {
+            if b {
+                let mut ld = String::with_capacity(3);
+                ld.push(c);
+                ld.push_str(":\\");
+                // Convert an UTF-8 string to a null-delimited UTF-16 string
+                let mut ld_utf16: Vec<u16> = ld.encode_utf16().collect();
+                ld_utf16.push(0);
+                let ld_type = unsafe { GetDriveTypeW(PCWSTR::from_raw(ld_utf16.as_mut_ptr())) };
+                if ld_type != 3 {
+                    return None; // not a fixed logical drive
+                }
+                let mut fs_name = [0u16; 261];
+                let fs_type = unsafe { GetVolumeInformationW(PCWSTR::from_raw(ld_utf16.as_ptr()), None, None, None, None, Some(&mut fs_name)) }
+                    .map(|_| String::from_utf16_lossy(&fs_name[..fs_name.iter().position(|&c| c == 0).unwrap_or(fs_name.len())]));
+                match fs_type {
+                    Ok(fs_type) if prune.is_pruned_fs(&fs_type) => None,
+                    _ => Some(PathBuf::from(ld)),
+                }
+            } else {
+                None // not a logical drive
+            }
+        })
+        .collect::<Vec<PathBuf>>();
+    Ok(ld_fix)
+}
+
+/// Undo the octal escapes of the space, tab, newline and backslash in a field of /proc/self/mounts
+#[cfg(target_os = "linux")]
+fn unescape(field: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let octal = field.get(i + 1..i + 4).filter(|_| field[i] == b'\\');
+        match octal.and_then(|octal| u8::from_str_radix(std::str::from_utf8(octal).ok()?, 8).ok()) {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(field[i]);
+                i += 1;
+            }
+        }
+    }
+    unescaped
+}
+
+/// The mount points of the file systems listed in `mounts`, in the format of /proc/self/mounts, whose type isn't pruned
+#[cfg(target_os = "linux")]
+fn local_mount_points(mounts: &[u8], prune: &Prune) -> Vec<PathBuf> {
+    let mut mount_points = mounts
+        .split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let mut fields = line.split(|&b| b == b' ');
+            let (_, mount_point, fs_type) = (fields.next()?, fields.next()?, fields.next()?);
+            if prune.is_pruned_fs(&String::from_utf8_lossy(fs_type)) {
+                return None;
+            }
+            Some(PathBuf::from(OsString::from_vec(unescape(mount_point)))) // raw bytes, may not be UTF-8
+        })
+        .collect::<Vec<PathBuf>>();
+    mount_points.sort();
+    mount_points.dedup();
+    mount_points
+}
+
+/// The mount points of the local file systems, such as `/` and `/home`
+#[cfg(target_os = "linux")]
+fn mounted_roots(prune: &Prune) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mounts = std::fs::read("/proc/self/mounts").map_err(|e| format!("/proc/self/mounts: {e}"))?;
+    Ok(local_mount_points(&mounts, prune))
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn mounted_roots(_prune: &Prune) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    Ok(vec![PathBuf::from("/")])
+}
+
+/// The roots to walk, but those in PRUNEPATHS or below
+pub fn roots(prune: &Prune) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut roots = mounted_roots(prune)?;
+    roots.retain(|root| !prune.is_pruned_root(root));
+    Ok(roots)
+}
+
+/// Walk the roots, without descending into the pruned directories. On Unix, a walk stays on its file system :
+/// the other local ones are roots of their own. A root met below another one is left to its own walk, so that
+/// no path is written twice.
+pub fn walk<'a>(roots: &'a [PathBuf], prune: &'a Prune) -> impl Iterator<Item = DirEntry> + 'a {
+    roots.iter().flat_map(move |root| {
+        WalkDir::new(root)
+            .same_file_system(cfg!(unix))
+            .into_iter()
+            .filter_entry(move |entry| !prune.is_pruned(entry) && (entry.depth() == 0 || !roots.iter().any(|root| root == entry.path())))
+            .filter_map(Result::ok)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::unnecessary_cast, clippy::bool_assert_comparison)]
+    fn dwordbits_ok() {
+        let mut bits = DwordBits::new(12 as u32);
+        assert_eq!(bits.next(), Some(false));
+        assert_eq!(bits.next(), Some(false));
+        assert_eq!(bits.next(), Some(true));
+        assert_eq!(bits.next(), Some(true));
+        bits.for_each(|b| assert_eq!(b, false));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pruned_roots_ok() {
+        let mut prune = Prune::default();
+        let all = roots(&prune).unwrap();
+        assert!(!all.is_empty());
+        prune.set_paths(&all[0].to_string_lossy());
+        assert!(roots(&prune).unwrap().iter().all(|root| !root.starts_with(&all[0])));
+        prune.set_paths("/");
+        assert!(roots(&prune).unwrap().is_empty());
+    }
+
+    #[test]
+    fn nested_roots_ok() {
+        let root = std::env::temp_dir().join(format!("roots-{}", std::process::id()));
+        for dir in ["a/b/c", "d"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let roots = [root.clone(), root.join("a/b"), root.join("a")];
+        let mut paths = walk(&roots, &Prune::default())
+            .map(|entry| entry.path().strip_prefix(&root).unwrap().to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+        assert_eq!(paths, ["", "a", "a/b", "a/b/c", "d"].map(PathBuf::from));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn local_mount_points_ok() {
+        let mounts = b"\
+proc /proc proc rw,relatime 0 0
+sysfs /sys sysfs rw,relatime 0 0
+/dev/sda2 / ext4 rw,relatime 0 0
+tmpfs /run tmpfs rw,nosuid,nodev 0 0
+cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid 0 0
+/dev/sda1 /boot/efi vfat rw,relatime 0 0
+/dev/sdb1 /srv/mes\\040donn\xc3\xa9es xfs rw,relatime 0 0
+/dev/sdc1 /srv/caf\xe9 ext4 rw,relatime 0 0
+server:/export /mnt/nfs nfs4 rw,relatime 0 0
+/dev/sda2 / ext4 rw,relatime 0 0
+";
+        let expected = [&b"/"[..], b"/boot/efi", b"/srv/caf\xe9", "/srv/mes données".as_bytes()];
+        assert!(local_mount_points(mounts, &Prune::default())
+            .iter()
+            .map(|root| root.as_os_str().as_encoded_bytes())
+            .eq(expected));
+        assert_eq!(unescape(b"a\\011b\\134\\0"), b"a\tb\\\\0");
+    }
+}